@@ -0,0 +1,39 @@
+use crate::{AddArgs, IO, Timesheet};
+use crate::report::interval::get_intervals;
+use crate::storage::NewRecord;
+
+/// Adds a clock in and a clock out in one go, if they don't overlap the
+/// intervals already on that day.
+pub fn add_cmd(timesheet: &Timesheet, args: AddArgs) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let today = chrono::Local::now().date_naive();
+    let date = args.date.map_or(Ok(today), |date| date.resolve(today))?;
+    let start = date.and_time(args.range.start);
+    let end = date.and_time(args.range.end);
+
+    // Incomplete intervals count too, so the new one can't land inside a forgotten clock out.
+    // Intervals may touch, since a clock out comes before a clock in at the same time
+    let overlapping = get_intervals(storage, date, date)?
+        .into_iter()
+        .find(|interval| interval.start < end && interval.end > start);
+    if let Some(interval) = overlapping {
+        return Err(anyhow::anyhow!(
+            "{start} - {} overlaps the interval {} - {}. Nothing was saved",
+            end.time(), interval.start, interval.end.time()
+        ));
+    }
+
+    let record = |timestamp, io| NewRecord {
+        timestamp,
+        io,
+        project: args.project.clone(),
+        tag: args.tag.clone(),
+        note: args.note.clone(),
+        correction: None,
+    };
+    let ids = storage.insert_all(vec![record(start, IO::I), record(end, IO::O)])?;
+    for id in ids {
+        println!("added: {}", storage.get(id)?.expect("The entry was just added"));
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
-use clap::{ValueEnum, Subcommand, command, };
-pub use clap::Parser;
-use chrono::{NaiveDate, NaiveTime};
+use clap::{ValueEnum, Subcommand};
+use clap::Parser;
+use crate::time_expr::{Age, DateExpr, TimeExpr, TimeRange};
+use serde::Serialize;
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
@@ -12,37 +13,149 @@ pub struct Cli {
     /// Specify the path to the sqlite databse.db3. Defaults to XDG data home.
     #[arg(long)]
     pub db_path: Option<PathBuf>,
+    /// Never ask questions. Anything that needs an answer fails instead.
+    #[arg(long, global = true)]
+    pub no_input: bool,
 }
 
 #[derive(Debug, Subcommand)]
 #[command(arg_required_else_help = true)]
 pub enum Commands {
-    /// Clock in or out
-    Clock(ClockArgs),
+    /// Clock in or out. Leave out i/o to do the opposite of the last entry
+    #[command(visible_alias = "punch")]
+    Clock {
+        #[clap(flatten)]
+        args: ClockArgs,
+        /// Don't ask: carry on if the system clock can't be checked or is off.
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Add a whole interval at once, e.g. a meeting you forgot to clock
+    Add(AddArgs),
     /// View timesheet and reports
     Report(ReportArgs),
+    /// Show whether you are clocked in, since when, and today's total so far
+    Status(StatusArgs),
+    /// Print the clock state for a shell prompt, without checking the time server
+    Prompt(PromptArgs),
     /// Fix an entry by ID
     Fix {
         id: i64, 
+        /// Save the change without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
         #[clap(flatten)]
         args: ClockArgs
     },
     /// Delete an entry by ID
     Delete {
-        id: i64
-    }
+        id: i64,
+        /// Delete the entry without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// List, restore and purge deleted entries
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Undo the last change to your entries
+    Undo,
+    /// Redo the last change you undid
+    Redo,
+    /// List changes to your entries, newest first
+    History {
+        /// How many changes to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Walk through incomplete intervals on a day and fix them one by one
+    Repair(RepairArgs),
+    /// View and change settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrashAction {
+    /// Show deleted entries, most recently deleted first
+    List,
+    /// Bring a deleted entry back
+    Restore {
+        id: i64,
+    },
+    /// Remove deleted entries for good
+    Purge {
+        /// Only remove entries deleted at least this long ago, e.g. 30d or 2 weeks
+        #[arg(long, default_value = "30d")]
+        older_than: Age,
+        /// Remove them without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Show every setting and its value
+    List,
+    /// Show the value of a setting
+    Get {
+        key: String,
+    },
+    /// Change a setting
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Put a setting back to its default
+    Unset {
+        key: String,
+    },
 }
 
 #[derive(Parser, Debug, Clone)]
 pub struct ClockArgs {
-    /// Specify whether you are clocking in or out
-    pub io: IO,
-    /// Clock time, 24hr. Defaults to current system time. Fmt. HH:MM:SS.
+    /// Specify whether you are clocking in or out. Defaults to the opposite of the previous entry.
+    pub io: Option<IO>,
+    /// Clock time. Defaults to current system time. E.g. 8:00, 17:30:15, 5pm, now, -15m, "15 minutes ago", "yesterday 17:30".
+    #[arg(short, long, allow_hyphen_values = true)]
+    pub time: Option<TimeExpr>,
+    /// Clock date. Defaults to today. E.g. 2023-05-31, yesterday, "last monday", "3 days ago".
+    #[arg(short, long, allow_hyphen_values = true)]
+    pub date: Option<DateExpr>,
+    /// Project (e.g. client) the time is billed to.
     #[arg(short, long)]
-    pub time: Option<NaiveTime>,
-    /// Clock date. Defaults to today. Fmt. YYYY-MM-DD.
+    pub project: Option<String>,
+    /// Tag describing the kind of work, e.g. meeting.
+    #[arg(long)]
+    pub tag: Option<String>,
+    /// Note on what you worked on.
     #[arg(short, long)]
-    pub date: Option<NaiveDate>,
+    pub note: Option<String>,
+    /// Save the entry even if it leaves two clock ins or outs in a row.
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct AddArgs {
+    /// Start and end of the interval, e.g. 09:00-10:30 or 9am-5pm.
+    pub range: TimeRange,
+    /// Date of the interval. Defaults to today. E.g. 2023-05-31, yesterday, "last monday".
+    #[arg(short, long, allow_hyphen_values = true)]
+    pub date: Option<DateExpr>,
+    /// Project (e.g. client) the time is billed to.
+    #[arg(short, long)]
+    pub project: Option<String>,
+    /// Tag describing the kind of work, e.g. meeting.
+    #[arg(long)]
+    pub tag: Option<String>,
+    /// Note on what you worked on.
+    #[arg(short, long)]
+    pub note: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -50,12 +163,55 @@ pub struct ReportArgs {
     /// Timesheet style. Defaults to fancy.
     #[arg(value_enum)]
     pub report_style: Option<ReportStyle>,
-    /// Date to view. Defaults to today. Fmt. YYYY-MM-DD.
-    #[arg(short, long)]
-    pub date: Option<NaiveDate>,
+    /// Date to view. Defaults to today. E.g. 2023-05-31, yesterday, "last monday".
+    #[arg(short, long, allow_hyphen_values = true)]
+    pub date: Option<DateExpr>,
     /// View yesterday's reports. Overrides the --date option.
     #[arg(short, long)]
     pub yesterday: bool,
+    /// View the week (Monday to Sunday) containing --date, day by day.
+    #[arg(short, long, conflicts_with_all = ["month", "from"])]
+    pub week: bool,
+    /// View the calendar month containing --date, day by day.
+    #[arg(short, long, conflicts_with_all = ["week", "from"])]
+    pub month: bool,
+    /// First day of a custom date range, day by day. E.g. 2023-05-31 or "last monday".
+    #[arg(long, allow_hyphen_values = true)]
+    pub from: Option<DateExpr>,
+    /// Last day of a custom date range. Defaults to today.
+    #[arg(long, requires = "from", allow_hyphen_values = true)]
+    pub to: Option<DateExpr>,
+    /// How to count shifts that cross midnight. Defaults to the `overnight` setting.
+    #[arg(long, value_enum)]
+    pub overnight: Option<OvernightRule>,
+    /// Output format. Defaults to text. JSON and CSV ignore the report style.
+    #[arg(long, value_enum)]
+    pub format: Option<ReportFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct StatusArgs {
+    /// Print one tab separated line for scripts: in/out/none, last entry, seconds since, seconds today, complete/incomplete
+    #[arg(long)]
+    pub terse: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RepairArgs {
+    /// Day to repair. Defaults to today. E.g. 2023-05-31, yesterday, "last monday".
+    #[arg(short, long, allow_hyphen_values = true)]
+    pub date: Option<DateExpr>,
+}
+
+#[derive(Parser, Debug)]
+#[command(after_help = format!("Placeholders: {}", crate::prompt::PLACEHOLDERS))]
+pub struct PromptArgs {
+    /// What to print while clocked in
+    #[arg(default_value = "{state} {elapsed}")]
+    pub template: String,
+    /// What to print while clocked out or before the first entry. Defaults to TEMPLATE once there are entries
+    #[arg(long)]
+    pub clocked_out: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -66,7 +222,41 @@ pub enum ReportStyle {
     Fancy
 }
 
-#[derive(Parser, Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Human readable tables
+    Text,
+    /// Records, intervals and totals as JSON
+    Json,
+    /// Records, intervals and totals as CSV, one row each
+    Csv
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OvernightRule {
+    /// Count the hours before midnight and after midnight towards their own days
+    Split,
+    /// Count the whole shift towards the day it started
+    Start,
+    /// Count the whole shift towards the day it ended
+    End
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimeCheckPolicy {
+    /// Ask whether to carry on, if there is someone at a terminal to ask
+    Prompt,
+    /// Carry on with a warning
+    Accept,
+    /// Fail without saving
+    Reject,
+    /// Don't check the system clock at all
+    SkipCheck
+}
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum IO {
     /// Clock in
     I,
@@ -83,6 +273,16 @@ impl std::fmt::Display for IO {
     }
 }
 
+impl IO {
+    /// "in" or "out", for messages
+    pub fn as_word(&self) -> &'static str {
+        match self {
+            Self::I => "in",
+            Self::O => "out",
+        }
+    }
+}
+
 impl TryFrom::<&str> for IO {
     type Error = anyhow::Error;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
@@ -1,53 +1,225 @@
 use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use ntp::packet::Packet;
+use std::io::IsTerminal;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use crate::{Config, Error, TimeCheckPolicy, time_expr::Age};
+use crate::config::{read_param, save_setting};
 
-const NPT_ADDR: &str = "time.nist.gov:123";
-// const PERMISSIBLE_ERROR_MIN: u64 = 15;
-const PERMISSIBLE_ERROR_MIN: u64 = 15;
-
-pub fn check_time() -> Result<()> {
-    let sys_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    let nist_time = get_time();
-    let diff = diff(sys_time + 2208988800_u64, nist_time?);
-    if diff <= 60 * PERMISSIBLE_ERROR_MIN {
+/// Seconds from the start of NTP time (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// Where the last clock check is kept in the `params` table
+const OFFSET_PARAM: &str = "ntp_offset";
+const CHECKED_AT_PARAM: &str = "ntp_checked_at";
+
+/// The result of the last successful clock check.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockCheck {
+    /// How far the time server was ahead of the system clock
+    pub offset: Duration,
+    /// System time of the check
+    pub checked_at: NaiveDateTime,
+}
+
+impl ClockCheck {
+    /// The last check saved in the database, if any.
+    pub fn load(conn: &sqlite::Connection) -> Result<Option<Self>> {
+        let (Some(offset), Some(checked_at)) = (read_param(conn, OFFSET_PARAM)?, read_param(conn, CHECKED_AT_PARAM)?) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            offset: Duration::seconds(offset.parse()?),
+            checked_at: NaiveDateTime::parse_from_str(&checked_at, "%Y-%m-%d %H:%M:%S")?,
+        }))
+    }
+
+    fn save(&self, conn: &sqlite::Connection) -> Result<()> {
+        save_setting(conn, OFFSET_PARAM, Some(&self.offset.num_seconds().to_string()))?;
+        save_setting(conn, CHECKED_AT_PARAM, Some(&self.checked_at.format("%Y-%m-%d %H:%M:%S").to_string()))
+    }
+
+    /// "3s slow", "1h fast" or "right on time".
+    pub fn describe_offset(&self) -> String {
+        match self.offset.num_seconds() {
+            0 => "right on time".to_owned(),
+            seconds if seconds > 0 => format!("{} slow", Age(self.offset)),
+            _ => format!("{} fast", Age(-self.offset)),
+        }
+    }
+}
+
+/// The offset from the last clock check, or from a new one if that is
+/// older than `ntp_recheck`.
+pub fn current_offset(conn: &sqlite::Connection, config: &Config) -> Result<Duration> {
+    let now = chrono::Local::now().naive_local();
+    if let Some(check) = ClockCheck::load(conn)? {
+        // A check from the future means the clock has been turned back since
+        if check.checked_at <= now && now - check.checked_at < config.ntp_recheck.0 {
+            return Ok(check.offset);
+        }
+    }
+    let offset = measure_offset(config)?;
+    ClockCheck { offset, checked_at: now }.save(conn)?;
+    Ok(offset)
+}
+
+/// Checks the system clock against the time servers in `config`, or against
+/// the last check if it is recent enough.
+pub fn check_time(conn: &sqlite::Connection, config: &Config) -> Result<()> {
+    let offset = current_offset(conn, config)?;
+    let tolerance = config.ntp_tolerance;
+    if offset.abs() <= tolerance.0 {
         Ok(())
     } else {
-        Err(anyhow::anyhow!("system clock is more than {PERMISSIBLE_ERROR_MIN} minute out of date"))
+        let direction = if offset > Duration::zero() { "slow" } else { "fast" };
+        Err(anyhow::anyhow!(
+            "system clock is {direction} by {}, more than the {tolerance} allowed by ntp_tolerance",
+            Age(offset.abs())
+        ))
     }
 }
 
-pub fn prompt_err(error_msg: &str) -> Result<()> {
-    println!("Whoops! Error: {error_msg}. Are you sure you want to continue? (y/n)");
+/// How far the time servers are ahead of the system clock. Servers are
+/// tried in order until one answers.
+pub fn measure_offset(config: &Config) -> Result<Duration> {
+    let timeout = config.ntp_timeout.0.to_std()?;
+    let mut failures = vec![];
+    for server in config.ntp_servers.iter() {
+        match query(server, timeout) {
+            Ok(offset) => return Ok(offset),
+            Err(err) => failures.push(format!("{server}: {err}")),
+        }
+    }
+    Err(anyhow::anyhow!("failed to communicate with time server ({})", failures.join("; ")))
+}
 
-    let mut input = String::with_capacity(2);
-    std::io::stdin().read_line(&mut input)?;
+/// Asks one time server for the time.
+fn query(server: &str, timeout: std::time::Duration) -> Result<Duration> {
+    let address = server.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no address found"))?;
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+        SocketAddr::V6(_) => "[::]:0".parse()?,
+    };
+    let socket = UdpSocket::bind(local)?;
+    // Only take answers from the server that was asked
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    let request = Packet::new_client();
+    let sent = request.transmit_time;
+    socket.send(&Vec::<u8>::from(request))?;
 
-    match input.trim() {
-        "y" | "Y" | "yes" | "YES" | "Yes" => Ok(()),
-        _ => Err(anyhow::anyhow!("User-initiated shutdown"))
+    let mut buffer = [0; 48];
+    let length = socket.recv(&mut buffer)?;
+    let packet = Packet::try_from(std::io::Cursor::new(&buffer[..length]))
+        .map_err(|err| anyhow::anyhow!("bad answer: {err}"))?;
+    // A reply echoes the request's transmit time, so stale or forged ones show
+    if packet.orig_time != sent {
+        anyhow::bail!("bad answer: it does not match the request");
     }
+    let server_time = i64::from(packet.transmit_time.sec) - NTP_UNIX_OFFSET;
+    Ok(Duration::seconds(server_time - chrono::Utc::now().timestamp()))
 }
 
-pub fn shutdown(conn: sqlite::Connection) -> ! {
-    println!("Shutting down");
-    drop(conn);
-    panic!("Exiting");
+/// Checks the system clock. If it cannot be trusted, carries on or not as
+/// the `time_check_failure` setting says, or regardless if `yes`.
+pub fn verify_system_clock(conn: &sqlite::Connection, config: &Config, yes: bool) -> Result<(), Error> {
+    if config.time_check_failure == TimeCheckPolicy::SkipCheck {
+        return Ok(());
+    }
+    match check_time(conn, config) {
+        Ok(()) => Ok(()),
+        Err(err) => on_failed_check(&err.to_string(), config, yes),
+    }
 }
 
-fn get_time() -> Result<u64> {
-    let response = ntp::request(NPT_ADDR);
-    if response.is_err() {
-        return Err(anyhow::anyhow!("failed to communicate with time server"));
+/// How much to add to the system time to match the time server, or `None`
+/// if it is right on time. If the offset can't be measured, carries on with
+/// the system time as it is, or not, like [`verify_system_clock`].
+pub fn system_clock_correction(conn: &sqlite::Connection, config: &Config, yes: bool) -> Result<Option<Duration>, Error> {
+    if config.time_check_failure == TimeCheckPolicy::SkipCheck {
+        return Ok(None);
+    }
+    match current_offset(conn, config) {
+        Ok(offset) => Ok((!offset.is_zero()).then_some(offset)),
+        Err(err) => on_failed_check(&err.to_string(), config, yes).map(|()| None),
     }
-    let ntp_time = response.unwrap().transmit_time.sec;
-    Ok(ntp_time as u64)
 }
 
-fn diff(a: u64, b: u64) -> u64 {
-    if a > b {
-        a - b
-    } else {
-        b - a
+fn on_failed_check(message: &str, config: &Config, yes: bool) -> Result<(), Error> {
+    let failed = Error::TimeCheckFailed(message.to_owned());
+    if yes {
+        eprintln!("Warning: {message}. Carrying on because of --yes");
+        return Ok(());
     }
-}
\ No newline at end of file
+    match config.time_check_failure {
+        TimeCheckPolicy::Accept => {
+            eprintln!("Warning: {message}. Carrying on because time_check_failure is accept");
+            Ok(())
+        },
+        TimeCheckPolicy::SkipCheck => Ok(()),
+        TimeCheckPolicy::Reject => Err(failed),
+        TimeCheckPolicy::Prompt => prompt_err(message, failed, config.no_input),
+    }
+}
+
+/// Asks the user whether to carry on despite `error_msg`. Fails with
+/// `Error::UserAborted` if they say no, or with `unanswered` if there is no
+/// one to ask. With `no_input`, nobody is asked.
+pub fn prompt_err(error_msg: &str, unanswered: Error, no_input: bool) -> Result<(), Error> {
+    if !can_ask(no_input) {
+        return Err(unanswered);
+    }
+    println!("Whoops! Error: {error_msg}. Are you sure you want to continue? (y/n)");
+    match read_answer() {
+        Some(true) => Ok(()),
+        Some(false) => Err(Error::UserAborted),
+        None => Err(unanswered),
+    }
+}
+
+/// Asks the user a yes or no `question`. Fails with `Error::UserAborted`
+/// unless they say yes, or if there is no one to ask.
+pub fn confirm(question: &str, no_input: bool) -> Result<(), Error> {
+    if !can_ask(no_input) {
+        return Err(Error::UserAborted);
+    }
+    println!("{question} (y/n)");
+    match read_answer() {
+        Some(true) => Ok(()),
+        _ => Err(Error::UserAborted),
+    }
+}
+
+/// Asks the user `question` and returns their answer, or `None` if stdin
+/// is closed or `no_input` is set. Unlike [`confirm`], this reads piped
+/// answers too, for the repair wizard.
+pub fn ask(question: &str, no_input: bool) -> Option<String> {
+    if no_input {
+        return None;
+    }
+    println!("{question}");
+    read_line()
+}
+
+/// Whether a yes or no question can be answered. Scripts and editors may keep
+/// stdin open without anyone to answer, so only a terminal counts.
+fn can_ask(no_input: bool) -> bool {
+    !no_input && std::io::stdin().is_terminal()
+}
+
+/// Whether the user typed yes, or `None` if stdin is closed.
+fn read_answer() -> Option<bool> {
+    read_line().map(|input| matches!(input.as_str(), "y" | "Y" | "yes" | "YES" | "Yes"))
+}
+
+fn read_line() -> Option<String> {
+    let mut input = String::new();
+    match std::io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_owned()),
+    }
+}
@@ -0,0 +1,32 @@
+use crate::{Commands, Timesheet};
+
+use crate::add::add_cmd;
+use crate::clock::clock_cmd;
+use crate::config::config_cmd;
+use crate::delete::del;
+use crate::fix::fix;
+use crate::history::{history_cmd, redo_cmd, undo_cmd};
+use crate::prompt::prompt_cmd;
+use crate::repair::repair_cmd;
+use crate::report::{report_cmd, status_cmd};
+use crate::trash::trash_cmd;
+
+/// Runs a `timest` subcommand against `timesheet`.
+pub fn run(timesheet: &Timesheet, command: Commands) -> Result<(), anyhow::Error> {
+    match command {
+        Commands::Clock { args, yes } => clock_cmd(timesheet, args, yes)?,
+        Commands::Add(args) => add_cmd(timesheet, args)?,
+        Commands::Report(args) => report_cmd(timesheet, args)?,
+        Commands::Status(args) => status_cmd(timesheet, args)?,
+        Commands::Prompt(args) => prompt_cmd(timesheet, args)?,
+        Commands::Fix{id, yes, args} => fix(timesheet, id, args, yes)?,
+        Commands::Delete { id, yes } => del(timesheet, id, yes)?,
+        Commands::Trash { action } => trash_cmd(timesheet, action)?,
+        Commands::Undo => undo_cmd(timesheet)?,
+        Commands::Redo => redo_cmd(timesheet)?,
+        Commands::History { limit } => history_cmd(timesheet, limit)?,
+        Commands::Repair(args) => repair_cmd(timesheet, args)?,
+        Commands::Config { action } => config_cmd(timesheet, action)?,
+    };
+    Ok(())
+}
@@ -1,50 +1,78 @@
-use crate::{ClockArgs, IO, check_time::*};
-use chrono::NaiveDateTime;
-
-pub fn clock_cmd(conn: sqlite::Connection, args: ClockArgs) {
-    let operation = args.io;
-    let time = args.time.unwrap_or(
-        if check_time().is_err_and(
-            |err| prompt_err(&err.to_string()).is_err()
-        ) {
-            shutdown(conn);
-        } else { 
-            current_time()
+use crate::{ClockArgs, IO, Timesheet, check_time::*, sequence::*, time_expr::resolve_datetime};
+use crate::storage::{NewRecord, Record, Storage};
+use chrono::{Duration, NaiveDateTime};
+
+/// Clocks in or out. `yes` carries on without asking if the system clock
+/// can't be trusted.
+pub fn clock_cmd(timesheet: &Timesheet, args: ClockArgs, yes: bool) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let config = timesheet.config();
+    // Times relative to now are only as good as the system clock
+    let mut correction = None;
+    if args.time.is_none_or(|time| time.is_relative()) && config.check_time {
+        if config.correct_time {
+            correction = system_clock_correction(timesheet.connection(), config, yes)?;
+        } else {
+            verify_system_clock(timesheet.connection(), config, yes)?;
         }
-    );
-    let date = args.date.unwrap_or(current_date());
-    let datetime = date.and_time(time);
+    }
+    let datetime = resolve_datetime(args.time, args.date, current_datetime())?;
+    let datetime = match correction {
+        Some(offset) => {
+            let corrected = datetime + offset;
+            println!("Corrected {} to {} using the time server", datetime.format("%Y-%m-%d %H:%M:%S"), corrected.format("%Y-%m-%d %H:%M:%S"));
+            corrected
+        },
+        None => datetime,
+    };
 
+    let operation = match args.io {
+        Some(operation) => operation,
+        None => {
+            let operation = timesheet.infer_io(datetime)?;
+            println!("Clocking {} at {}", operation.as_word(), datetime.format("%Y-%m-%d %H:%M:%S"));
+            operation
+        }
+    };
 
-    
-    add_clock(&conn, datetime, operation).expect("Expected to be able to write to db");
+    validate_sequence(storage, datetime, operation, None, args.force, config.no_input)?;
+    add_clock(storage, datetime, operation, args.project, args.tag, args.note, correction.map(|offset| offset.num_seconds()))?;
+    Ok(())
 }
 
-const CLOCK_SQL: &str = "
-    INSERT INTO times (
-        timestamp, io
-    ) VALUES (
-        :datetime, :op
-    )
-";
-
-fn add_clock(conn: &sqlite::Connection, datetime: NaiveDateTime, operation: IO) -> Result<(), anyhow::Error> {
-
-    
-    let mut stmt = conn.prepare(CLOCK_SQL)?;
-    stmt.bind::<&[(_, sqlite::Value)]>(&[
-            (":datetime", datetime.format("%Y-%m-%d %H:%M:%S").to_string().into()), 
-            (":op", operation.to_string().into()),
-            ][..])?;
-    
-    stmt.next()?;
-    Ok(())
+/// Works out whether a clock at `datetime` is a clock in or out from the
+/// entries around it. Refuses to guess when either answer looks wrong.
+pub(crate) fn infer_io(storage: &dyn Storage, datetime: NaiveDateTime) -> Result<IO, anyhow::Error> {
+    let operation = match prev_entry(storage, datetime, None)? {
+        None | Some(Record { io: IO::O, .. }) => IO::I,
+        Some(Record { timestamp, io: IO::I, .. }) => {
+            if datetime - timestamp > Duration::hours(MAX_SHIFT_HOURS) {
+                return Err(anyhow::anyhow!(
+                    "the last entry is a clock in at {timestamp}, more than {MAX_SHIFT_HOURS} hours earlier. \
+                    Did you forget to clock out? Run `timest clock i` or `timest clock o` to say which you mean"
+                ));
+            }
+            IO::O
+        },
+    };
+
+    if let Some(Record { timestamp, io: next_operation, .. }) = next_entry(storage, datetime, None)? {
+        if next_operation == operation {
+            return Err(anyhow::anyhow!(
+                "clocking {} here would be followed by another clock {} at {timestamp}. \
+                Run `timest clock i` or `timest clock o` to say which you mean",
+                operation.as_word(), next_operation.as_word()
+            ));
+        }
+    }
+
+    Ok(operation)
 }
 
-fn current_time() -> chrono::NaiveTime {
-    chrono::Local::now().time()
+pub(crate) fn add_clock(storage: &dyn Storage, datetime: NaiveDateTime, operation: IO, project: Option<String>, tag: Option<String>, note: Option<String>, correction: Option<i64>) -> Result<i64, anyhow::Error> {
+    storage.insert(NewRecord { timestamp: datetime, io: operation, project, tag, note, correction })
 }
 
-fn current_date() -> chrono::NaiveDate {
-    chrono::Local::now().date_naive()
-}
\ No newline at end of file
+fn current_datetime() -> NaiveDateTime {
+    chrono::Local::now().naive_local()
+}
@@ -1,21 +1,249 @@
+use crate::{ConfigAction, OvernightRule, TimeCheckPolicy, Timesheet, time_expr::Age};
+use chrono::Duration;
+use clap::ValueEnum;
+use sqlite::State;
+
+/// Settings persisted in the `params` table. Anything not in the table
+/// takes its default. Environment variables like `TIMEST_CHECK_TIME`
+/// override both.
+#[derive(Debug, Clone)]
 pub struct Config {
-    pub check_time: bool
+    /// Check the system clock against a time server before clocking at the current time
+    pub check_time: bool,
+    /// How reports count shifts that cross midnight
+    pub overnight: OvernightRule,
+    /// Time servers (`host:port`) to check the system clock against, tried in order
+    pub ntp_servers: Vec<String>,
+    /// How far off the system clock may be
+    pub ntp_tolerance: Age,
+    /// How long to wait for each time server to answer
+    pub ntp_timeout: Age,
+    /// How long a clock check is trusted before asking a time server again
+    pub ntp_recheck: Age,
+    /// Correct the system time by the offset the clock check measured, instead of asking
+    pub correct_time: bool,
+    /// What to do when the system clock can't be checked or is off
+    pub time_check_failure: TimeCheckPolicy,
+    /// Never ask questions, as with `--no-input`. Not saved in the database
+    pub no_input: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            check_time: true,
+            overnight: OvernightRule::Split,
+            ntp_servers: vec!["time.nist.gov:123".to_owned()],
+            ntp_tolerance: Age(Duration::minutes(15)),
+            ntp_timeout: Age(Duration::seconds(5)),
+            ntp_recheck: Age(Duration::hours(1)),
+            correct_time: false,
+            time_check_failure: TimeCheckPolicy::Prompt,
+            no_input: false,
+        }
+    }
+}
+
+/// Every setting, with a description for `timest config list`.
+pub const SETTINGS: &[(&str, &str)] = &[
+    ("check_time", "Check the system clock against a time server when clocking without --time (true/false)"),
+    ("overnight", "How reports count shifts that cross midnight (split/start/end)"),
+    ("ntp_servers", "Time servers to check the system clock against, tried in order (comma separated host:port)"),
+    ("ntp_tolerance", "How far off the system clock may be (e.g. 15m, 30s)"),
+    ("ntp_timeout", "How long to wait for each time server to answer (e.g. 5s)"),
+    ("ntp_recheck", "How long to trust the last clock check before asking a time server again (e.g. 1h, 0s to always ask)"),
+    ("correct_time", "Clock at the time server's time instead of asking when the system clock is off (true/false)"),
+    ("time_check_failure", "What to do when the system clock can't be checked or is off (prompt/accept/reject/skip-check)"),
+];
+
+/// The environment variable that overrides `key`, e.g. `TIMEST_NTP_SERVERS`.
+pub fn env_var(key: &str) -> String {
+    format!("TIMEST_{}", key.to_uppercase())
+}
+
+impl Config {
+    /// Defaults, overridden by whatever is saved in the database, then by
+    /// the environment. Bad values in the database are warned about and
+    /// left at their default.
+    pub fn load(conn: &sqlite::Connection) -> Result<Self, anyhow::Error> {
+        let mut config = Self::default();
+        let mut stmt = conn.prepare("SELECT parameter, value FROM params")?;
+        while let State::Row = stmt.next()? {
+            let key = stmt.read::<String, _>("parameter")?;
+            let Some(value) = stmt.read::<Option<String>, _>("value")? else {
+                continue;
+            };
+            // Keep going on a bad value, so that `config set` and `config unset` can still fix it
+            if is_setting(&key) {
+                if let Err(err) = config.set(&key, &value) {
+                    eprintln!(
+                        "Warning: bad setting saved in database: {err}. Using the default {} until it is fixed with `timest config set {key}` or `timest config unset {key}`",
+                        Config::default().get(&key)?
+                    );
+                }
+            }
+        }
+        for (key, _) in SETTINGS.iter() {
+            if let Ok(value) = std::env::var(env_var(key)) {
+                config.set(key, &value)
+                    .map_err(|err| anyhow::anyhow!("bad setting in {}: {err}", env_var(key)))?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn get(&self, key: &str) -> Result<String, anyhow::Error> {
+        match key {
+            "check_time" => Ok(self.check_time.to_string()),
+            "overnight" => Ok(enum_name(self.overnight)),
+            "ntp_servers" => Ok(self.ntp_servers.join(",")),
+            "ntp_tolerance" => Ok(self.ntp_tolerance.to_string()),
+            "ntp_timeout" => Ok(self.ntp_timeout.to_string()),
+            "ntp_recheck" => Ok(self.ntp_recheck.to_string()),
+            "correct_time" => Ok(self.correct_time.to_string()),
+            "time_check_failure" => Ok(enum_name(self.time_check_failure)),
+            _ => Err(unknown_setting(key)),
+        }
+    }
+
+    /// Parses and validates `value` before changing the setting.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), anyhow::Error> {
+        match key {
+            "check_time" => self.check_time = parse_bool(key, value)?,
+            "overnight" => self.overnight = parse_enum(key, value)?,
+            "ntp_servers" => self.ntp_servers = parse_list(key, value)?,
+            "ntp_tolerance" => self.ntp_tolerance = parse_age(key, value)?,
+            "ntp_timeout" => {
+                let timeout = parse_age(key, value)?;
+                if timeout.0.is_zero() {
+                    return Err(anyhow::anyhow!("{key} has to be longer than 0s"));
+                }
+                self.ntp_timeout = timeout;
+            },
+            "ntp_recheck" => self.ntp_recheck = parse_age(key, value)?,
+            "correct_time" => self.correct_time = parse_bool(key, value)?,
+            "time_check_failure" => self.time_check_failure = parse_enum(key, value)?,
+            _ => return Err(unknown_setting(key)),
+        }
+        Ok(())
+    }
 }
 
-pub fn make_config_table(conn: &sqlite::Connection) -> Result<(), anyhow::Error> {
-    let make_config_table = "
-        CREATE TABLE IF NOT EXISTS params (
-            parameter TEXT PRIMARY KEY NOT NULL UNIQUE,
-            value TEXT
-        ) WITHOUT ROWID
-    ";
+pub fn config_cmd(timesheet: &Timesheet, action: ConfigAction) -> Result<(), anyhow::Error> {
+    let conn = timesheet.connection();
+    let mut config = timesheet.config().clone();
+    match action {
+        ConfigAction::List => {
+            let defaults = Config::default();
+            for (key, description) in SETTINGS.iter() {
+                let value = config.get(key)?;
+                let note = if std::env::var(env_var(key)).is_ok() {
+                    format!(" (from {})", env_var(key))
+                } else if value == defaults.get(key)? {
+                    " (default)".to_owned()
+                } else {
+                    String::new()
+                };
+                println!("{key} = {value}{note}");
+                println!("\t{description}");
+            }
+        },
+        ConfigAction::Get { key } => println!("{}", config.get(&key)?),
+        ConfigAction::Set { key, value } => {
+            config.set(&key, &value)?;
+            // Save the normalized value, e.g. `Split` as `split`
+            save_setting(conn, &key, Some(&config.get(&key)?))?;
+        },
+        ConfigAction::Unset { key } => {
+            if !is_setting(&key) {
+                return Err(unknown_setting(&key));
+            }
+            save_setting(conn, &key, None)?;
+        },
+    }
+    Ok(())
+}
+
+const SAVE_SQL: &str = "
+    INSERT INTO params (parameter, value) VALUES (:parameter, :value)
+    ON CONFLICT(parameter) DO UPDATE SET value = excluded.value
+";
+
+const UNSET_SQL: &str = "
+    DELETE FROM params WHERE parameter = :parameter
+";
 
-    conn.execute(make_config_table)?;
+/// The value saved under `key` in the `params` table, if any.
+pub fn read_param(conn: &sqlite::Connection, key: &str) -> Result<Option<String>, anyhow::Error> {
+    let mut stmt = conn.prepare("SELECT value FROM params WHERE parameter = ?")?;
+    stmt.bind((1, key))?;
+    match stmt.next()? {
+        State::Row => Ok(stmt.read::<Option<String>, _>(0)?),
+        State::Done => Ok(None),
+    }
+}
 
+/// Saves a setting, or goes back to its default if `value` is `None`. Also
+/// keeps other state in the `params` table, like the last clock check.
+pub fn save_setting(conn: &sqlite::Connection, key: &str, value: Option<&str>) -> Result<(), anyhow::Error> {
+    let mut stmt = match value {
+        Some(value) => {
+            let mut stmt = conn.prepare(SAVE_SQL)?;
+            stmt.bind((":value", value))?;
+            stmt
+        },
+        None => conn.prepare(UNSET_SQL)?,
+    };
+    stmt.bind((":parameter", key))?;
+    stmt.next()?;
     Ok(())
 }
 
-fn populate_config_table(conn: &sqlite::Connection) -> Result<(), anyhow::Error> {
-    // Figure out if there are appropriate keys in config table. If not, insert into.
-    todo!()
-}
\ No newline at end of file
+fn is_setting(key: &str) -> bool {
+    SETTINGS.iter().any(|(setting, _)| *setting == key)
+}
+
+fn unknown_setting(key: &str) -> anyhow::Error {
+    let known = SETTINGS.iter().map(|(key, _)| *key).collect::<Vec<_>>().join(", ");
+    anyhow::anyhow!("unknown setting `{key}`. Settings are: {known}")
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, anyhow::Error> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(anyhow::anyhow!("`{value}` is not a valid value for {key}. Expected true or false")),
+    }
+}
+
+fn parse_list(key: &str, value: &str) -> Result<Vec<String>, anyhow::Error> {
+    let items: Vec<String> = value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if items.is_empty() {
+        return Err(anyhow::anyhow!("{key} needs at least one value"));
+    }
+    Ok(items)
+}
+
+fn parse_age(key: &str, value: &str) -> Result<Age, anyhow::Error> {
+    value.parse().map_err(|_| anyhow::anyhow!(
+        "`{value}` is not a valid value for {key}. Expected an amount of time like 15m or 30s"
+    ))
+}
+
+fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T, anyhow::Error> {
+    T::from_str(value, true).map_err(|_| {
+        let expected = T::value_variants().iter().map(|variant| enum_name(variant.clone())).collect::<Vec<_>>().join(", ");
+        anyhow::anyhow!("`{value}` is not a valid value for {key}. Expected one of: {expected}")
+    })
+}
+
+fn enum_name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value()
+        .expect("Settings have no skipped variants")
+        .get_name()
+        .to_owned()
+}
@@ -1,9 +1,15 @@
-const DEL_SQL: &str = "
-    DELETE FROM times WHERE ID = ?
-";
+use crate::{Error, check_time::confirm};
 
-pub fn del(connection: sqlite::Connection, id: i64) {
-    let mut stmt = connection.prepare(DEL_SQL).unwrap();
-    stmt.bind((1, id)).unwrap();
-    let _ = stmt.next().unwrap();
-}
\ No newline at end of file
+pub fn del(timesheet: &crate::Timesheet, id: i64, yes: bool) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let record = storage.get(id)?.ok_or(Error::BadId(id))?;
+
+    println!("delete: {record}");
+    if !yes {
+        confirm("Delete this entry?", timesheet.config().no_input)?;
+    }
+
+    storage.delete(id)?;
+    println!("Moved to the trash. Run `timest trash restore {id}` to bring it back");
+    Ok(())
+}
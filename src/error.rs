@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// Failures that scripts may want to tell apart. Each has its own exit code;
+/// anything else exits with 1, and bad command line arguments exit with 2.
+///
+/// | Code | Error |
+/// |------|-------|
+/// | 3 | [`Error::BadId`] |
+/// | 4 | [`Error::DatabaseLocked`] |
+/// | 5 | [`Error::InvalidSequence`] |
+/// | 6 | [`Error::TimeCheckFailed`] |
+/// | 7 | [`Error::UserAborted`] |
+#[derive(Debug)]
+pub enum Error {
+    /// There is no entry with this id
+    BadId(i64),
+    /// Another process is using the database
+    DatabaseLocked,
+    /// The entry would break the in/out sequence, and no one confirmed it
+    InvalidSequence(String),
+    /// The system clock could not be checked against a time server, or is
+    /// wrong, and no one confirmed it
+    TimeCheckFailed(String),
+    /// The user answered no when asked to confirm
+    UserAborted,
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::BadId(_) => 3,
+            Error::DatabaseLocked => 4,
+            Error::InvalidSequence(_) => 5,
+            Error::TimeCheckFailed(_) => 6,
+            Error::UserAborted => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadId(id) => write!(f, "there is no entry with id {id}. See `timest report simple` for ids"),
+            Error::DatabaseLocked => write!(f, "the database is locked by another program. Try again in a moment"),
+            Error::InvalidSequence(message) => write!(f, "{message}. Nothing was saved. Use --force to save it anyway"),
+            Error::TimeCheckFailed(message) => write!(f, "{message}. Nothing was saved. Give the time yourself with --time, or carry on anyway with `clock --yes` or the time_check_failure setting"),
+            Error::UserAborted => write!(f, "aborted. Nothing was saved"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// SQLite result codes for a database another connection is using.
+const SQLITE_BUSY: isize = 5;
+const SQLITE_LOCKED: isize = 6;
+
+/// The exit code for `err`, looking through its causes for a known [`Error`].
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<Error>() {
+            return err.exit_code();
+        }
+        if let Some(sqlite::Error { code: Some(code), .. }) = cause.downcast_ref::<sqlite::Error>() {
+            // Extended result codes keep the primary code in the low byte
+            if matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED) {
+                return Error::DatabaseLocked.exit_code();
+            }
+        }
+    }
+    1
+}
@@ -1,32 +1,36 @@
-use crate::check_time::*;
+use crate::{Error, Timesheet, check_time::*, sequence::validate_sequence, time_expr::amend_datetime};
+use crate::storage::RecordUpdate;
 
-const MODIFY_SQL: &str = "
-    UPDATE times
-    SET timestamp = :timestamp, io = :io
-    WHERE id = :id
-";
+/// Changes only the parts of entry `id` given in `args`. `yes` saves the
+/// change without confirming it, but a failed clock check is still up to
+/// the `time_check_failure` setting.
+pub fn fix(timesheet: &Timesheet, id: i64, args: crate::ClockArgs, yes: bool) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let config = timesheet.config();
+    let old = storage.get(id)?.ok_or(Error::BadId(id))?;
+    if args.time.is_some_and(|time| time.is_relative()) && config.check_time {
+        verify_system_clock(timesheet.connection(), config, false)?;
+    }
+    let datetime = amend_datetime(args.time, args.date, old.timestamp, chrono::Local::now().naive_local())?;
 
-pub fn fix(connection: sqlite::Connection, id: i64, args: crate::ClockArgs) {
-    let time = args.time.unwrap_or(
-        if check_time().is_err_and(
-            |err| prompt_err(&err.to_string()).is_err()
-        ) {
-            shutdown(connection);
-        } else {
-            chrono::Local::now().time()
-        }
-    );
-    let date = args.date.unwrap_or(chrono::Local::now().date_naive());
-    let datetime = date.and_time(time);
-    let datetime_str = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+    let io = args.io.unwrap_or(old.io);
+    if datetime != old.timestamp || io != old.io {
+        validate_sequence(storage, datetime, io, Some((id, old.timestamp)), args.force, config.no_input)?;
+    }
 
-    let io = args.io.to_string();
+    let update = RecordUpdate {
+        timestamp: Some(datetime),
+        io: args.io,
+        project: args.project,
+        tag: args.tag,
+        note: args.note,
+    };
+    println!("before: {old}");
+    println!("after:  {}", update.apply(&old));
+    if !yes {
+        confirm("Save this change?", config.no_input)?;
+    }
 
-    let mut stmt = connection.prepare(MODIFY_SQL).unwrap();
-    stmt.bind::<&[(_, sqlite::Value)]>(&[
-        (":timestamp", datetime_str.into()),
-        (":io", io.into()),
-        (":id", id.into()),
-    ][..]).unwrap();
-    let _ = stmt.next().unwrap();
-}
\ No newline at end of file
+    storage.update(id, update)?;
+    Ok(())
+}
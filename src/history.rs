@@ -0,0 +1,51 @@
+use crate::Timesheet;
+use crate::storage::{Change, ChangeSource, Record};
+
+/// An entry as logged in a change, or "(none)" where it did not exist.
+fn describe(record: &Option<Record>) -> String {
+    record.as_ref().map_or("(none)".to_owned(), |record| record.to_string())
+}
+
+fn describe_change(change: &Change) -> String {
+    format!("change {} ({} of entry {})", change.id, change.kind.as_str(), change.entry_id)
+}
+
+pub fn undo_cmd(timesheet: &Timesheet) -> Result<(), anyhow::Error> {
+    let Some(change) = timesheet.storage().undo()? else {
+        println!("Nothing to undo");
+        return Ok(());
+    };
+    println!("Undid {}", describe_change(&change));
+    println!("before: {}", describe(&change.new));
+    println!("after:  {}", describe(&change.old));
+    Ok(())
+}
+
+pub fn redo_cmd(timesheet: &Timesheet) -> Result<(), anyhow::Error> {
+    let Some(change) = timesheet.storage().redo()? else {
+        println!("Nothing to redo");
+        return Ok(());
+    };
+    println!("Redid {}", describe_change(&change));
+    println!("before: {}", describe(&change.old));
+    println!("after:  {}", describe(&change.new));
+    Ok(())
+}
+
+/// Prints the latest `limit` changes, newest first.
+pub fn history_cmd(timesheet: &Timesheet, limit: usize) -> Result<(), anyhow::Error> {
+    let changes = timesheet.storage().changes()?;
+    for change in changes.iter().rev().take(limit) {
+        let what = match (change.source, change.reverts) {
+            (ChangeSource::Edit, _) | (_, None) => change.kind.as_str().to_owned(),
+            (source, Some(reverts)) => format!("{} of {reverts}", source.as_str()),
+        };
+        println!("{:>4}  {}  {what:<10}  {} -> {}",
+            change.id,
+            change.changed_at.format("%Y-%m-%d %H:%M:%S"),
+            describe(&change.old),
+            describe(&change.new),
+        );
+    }
+    Ok(())
+}
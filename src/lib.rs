@@ -0,0 +1,48 @@
+//! Dead simple timesheet. Clock in, clock out, see time worked.
+//!
+//! [`Timesheet`] is the way in for tools built on timest. The `cli` module is
+//! the `timest` command line built on top of it.
+
+pub mod args;
+pub use args::*;
+
+pub mod cli;
+
+mod error;
+pub use error::{Error, exit_code};
+
+mod timesheet;
+pub use timesheet::Timesheet;
+
+mod clock;
+
+mod add;
+
+mod report;
+pub use report::interval::{Interval, IntervalStatus};
+
+pub mod storage;
+pub use storage::{Record, Storage};
+
+mod check_time;
+
+mod fix;
+
+mod delete;
+
+mod history;
+
+mod trash;
+
+mod repair;
+
+mod migrations;
+
+mod sequence;
+
+pub mod time_expr;
+
+pub mod config;
+pub use config::Config;
+
+mod prompt;
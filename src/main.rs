@@ -1,41 +1,31 @@
 use anyhow::Result;
 use platform_dirs::AppDirs;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
-pub mod args;
-use args::*;
+use clap::Parser;
+use timest::{Cli, Timesheet};
 
-mod clock;
-use clock::*;
-
-mod report;
-use report::*;
-
-mod check_time;
-
-mod fix;
-use fix::*;
-
-mod delete;
-use delete::del;
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(timest::exit_code(&err))
+        }
+    }
+}
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let cli = Cli::parse();
 
     let db_path = get_db_path(cli.db_path);
-    
-    let conn = sqlite::open(db_path).expect("Should be able to open .db3 database");
-    prepare_tables(&conn).expect("Expected available .db3 file");
-
-    match cli.command {
-        Commands::Clock(args) => clock_cmd(conn, args),
-        Commands::Report(args) => report_cmd(conn, args),
-        Commands::Fix{id, args} => fix(conn, id, args),
-        Commands::Delete { id } => del(conn, id),
-    };
-    
-    Ok(())
+    let mut timesheet = Timesheet::open(db_path)?;
+    if cli.no_input {
+        timesheet.disable_input();
+    }
 
+    timest::cli::run(&timesheet, cli.command)
 }
 
 #[cfg(target_os = "linux")]
@@ -72,25 +62,3 @@ fn get_db_path(user_path: Option<PathBuf>) -> impl AsRef<std::path::Path> + std:
 
     root_dir.join("timest.db3")
 }
-
-fn prepare_tables(conn: &sqlite::Connection) -> Result<(), anyhow::Error> {
-    let make_times_table = "
-        CREATE TABLE IF NOT EXISTS times (
-            id INTEGER PRIMARY KEY NOT NULL,
-            timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            io TEXT NOT NULL CHECK(io in ('i', 'o'))
-        );
-    ";
-
-    conn.execute(make_times_table)?;
-
-    let make_config_table = "
-        CREATE TABLE IF NOT EXISTS params (
-            parameter TEXT PRIMARY KEY NOT NULL UNIQUE,
-            value TEXT
-        ) WITHOUT ROWID
-    ";
-
-    conn.execute(make_config_table)?;
-    Ok(())
-}
@@ -0,0 +1,259 @@
+use std::path::{Path, PathBuf};
+
+/// A schema change, applied once to bring the database up to `version`.
+struct Migration {
+    version: i64,
+    sql: &'static str,
+}
+
+/// Every schema the database has had, oldest first. Never edit a migration
+/// once released; add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    // Tables as created by timest 1.0.2 and earlier
+    Migration {
+        version: 1,
+        sql: "
+            CREATE TABLE IF NOT EXISTS times (
+                id INTEGER PRIMARY KEY NOT NULL,
+                timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                io TEXT NOT NULL CHECK(io in ('i', 'o'))
+            );
+            CREATE TABLE IF NOT EXISTS params (
+                parameter TEXT PRIMARY KEY NOT NULL UNIQUE,
+                value TEXT
+            ) WITHOUT ROWID;
+        ",
+    },
+    // Projects and tags
+    Migration {
+        version: 2,
+        sql: "
+            ALTER TABLE times ADD COLUMN project TEXT;
+            ALTER TABLE times ADD COLUMN tag TEXT;
+        ",
+    },
+    // Change log for undo, redo and history
+    Migration {
+        version: 3,
+        sql: "
+            CREATE TABLE changes (
+                id INTEGER PRIMARY KEY NOT NULL,
+                changed_at TIMESTAMP NOT NULL,
+                entry_id INTEGER NOT NULL,
+                kind TEXT NOT NULL CHECK(kind in ('insert', 'update', 'delete')),
+                source TEXT NOT NULL CHECK(source in ('edit', 'undo', 'redo')),
+                reverts INTEGER REFERENCES changes(id),
+                old_timestamp TIMESTAMP,
+                old_io TEXT CHECK(old_io in ('i', 'o')),
+                old_project TEXT,
+                old_tag TEXT,
+                new_timestamp TIMESTAMP,
+                new_io TEXT CHECK(new_io in ('i', 'o')),
+                new_project TEXT,
+                new_tag TEXT
+            );
+            CREATE TRIGGER changes_no_update BEFORE UPDATE ON changes
+            BEGIN SELECT RAISE(ABORT, 'the change log is append-only'); END;
+            CREATE TRIGGER changes_no_delete BEFORE DELETE ON changes
+            BEGIN SELECT RAISE(ABORT, 'the change log is append-only'); END;
+        ",
+    },
+    // Deleted entries go to the trash
+    Migration {
+        version: 4,
+        sql: "
+            ALTER TABLE times ADD COLUMN deleted_at TIMESTAMP;
+        ",
+    },
+    // Notes on what was worked on
+    Migration {
+        version: 5,
+        sql: "
+            ALTER TABLE times ADD COLUMN note TEXT;
+            ALTER TABLE changes ADD COLUMN old_note TEXT;
+            ALTER TABLE changes ADD COLUMN new_note TEXT;
+        ",
+    },
+    // Corrections applied to the system time when clocking
+    Migration {
+        version: 6,
+        sql: "
+            ALTER TABLE times ADD COLUMN correction INTEGER;
+            ALTER TABLE changes ADD COLUMN old_correction INTEGER;
+            ALTER TABLE changes ADD COLUMN new_correction INTEGER;
+        ",
+    },
+    // Ids are never reused, even after the trash is purged, so that the
+    // change log never mixes up two entries
+    Migration {
+        version: 7,
+        sql: "
+            CREATE TABLE times_autoincrement (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                io TEXT NOT NULL CHECK(io in ('i', 'o')),
+                project TEXT,
+                tag TEXT,
+                deleted_at TIMESTAMP,
+                note TEXT,
+                correction INTEGER
+            );
+            INSERT INTO times_autoincrement (id, timestamp, io, project, tag, deleted_at, note, correction)
+            SELECT id, timestamp, io, project, tag, deleted_at, note, correction FROM times;
+            DROP TABLE times;
+            ALTER TABLE times_autoincrement RENAME TO times;
+            DELETE FROM sqlite_sequence WHERE name = 'times';
+            INSERT INTO sqlite_sequence (name, seq) VALUES ('times', max(
+                (SELECT ifnull(max(id), 0) FROM times),
+                (SELECT ifnull(max(entry_id), 0) FROM changes)
+            ));
+        ",
+    },
+    // Purging the trash is logged, so that undo knows to stop there
+    Migration {
+        version: 8,
+        sql: "
+            CREATE TABLE changes_with_purge (
+                id INTEGER PRIMARY KEY NOT NULL,
+                changed_at TIMESTAMP NOT NULL,
+                entry_id INTEGER NOT NULL,
+                kind TEXT NOT NULL CHECK(kind in ('insert', 'update', 'delete', 'purge')),
+                source TEXT NOT NULL CHECK(source in ('edit', 'undo', 'redo')),
+                reverts INTEGER REFERENCES changes(id),
+                old_timestamp TIMESTAMP,
+                old_io TEXT CHECK(old_io in ('i', 'o')),
+                old_project TEXT,
+                old_tag TEXT,
+                new_timestamp TIMESTAMP,
+                new_io TEXT CHECK(new_io in ('i', 'o')),
+                new_project TEXT,
+                new_tag TEXT,
+                old_note TEXT,
+                new_note TEXT,
+                old_correction INTEGER,
+                new_correction INTEGER
+            );
+            INSERT INTO changes_with_purge SELECT
+                id, changed_at, entry_id, kind, source, reverts,
+                old_timestamp, old_io, old_project, old_tag,
+                new_timestamp, new_io, new_project, new_tag,
+                old_note, new_note, old_correction, new_correction
+            FROM changes;
+            DROP TABLE changes;
+            ALTER TABLE changes_with_purge RENAME TO changes;
+            CREATE TRIGGER changes_no_update BEFORE UPDATE ON changes
+            BEGIN SELECT RAISE(ABORT, 'the change log is append-only'); END;
+            CREATE TRIGGER changes_no_delete BEFORE DELETE ON changes
+            BEGIN SELECT RAISE(ABORT, 'the change log is append-only'); END;
+        ",
+    },
+];
+
+pub const SCHEMA_VERSION_PARAM: &str = "schema_version";
+
+/// Brings the database at `db_path` up to the latest schema. Existing
+/// databases are backed up next to `db_path` before they are changed.
+pub fn migrate(conn: &sqlite::Connection, db_path: &Path) -> Result<(), anyhow::Error> {
+    let current = schema_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(anyhow::anyhow!(
+            "database schema version {current} is newer than this version of timest supports ({latest})"
+        ));
+    }
+    if current == latest {
+        return Ok(());
+    }
+
+    if current > 0 {
+        backup(conn, &backup_path(db_path, current))?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        conn.execute("BEGIN")?;
+        let result = conn.execute(migration.sql)
+            .and_then(|_| set_schema_version(conn, migration.version));
+        match result {
+            Ok(()) => conn.execute("COMMIT")?,
+            Err(err) => {
+                conn.execute("ROLLBACK")?;
+                return Err(anyhow::anyhow!(
+                    "failed to migrate database to schema version {}: {err}", migration.version
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Where the copy of a database at `version` is kept before migrating it.
+pub fn backup_path(db_path: &Path, version: i64) -> PathBuf {
+    let mut file_name = db_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".v{version}.bak"));
+    db_path.with_file_name(file_name)
+}
+
+/// The schema version of the database, or 0 for a brand new database.
+pub fn schema_version(conn: &sqlite::Connection) -> Result<i64, anyhow::Error> {
+    if !table_exists(conn, "times")? {
+        return Ok(0);
+    }
+
+    if table_exists(conn, "params")? {
+        let mut stmt = conn.prepare("SELECT value FROM params WHERE parameter = ?")?;
+        stmt.bind((1, SCHEMA_VERSION_PARAM))?;
+        if let sqlite::State::Row = stmt.next()? {
+            return Ok(stmt.read::<String, _>(0)?.parse()?);
+        }
+    }
+
+    // Databases from before schema versions were recorded
+    if column_exists(conn, "times", "project")? {
+        Ok(2)
+    } else {
+        Ok(1)
+    }
+}
+
+fn set_schema_version(conn: &sqlite::Connection, version: i64) -> Result<(), sqlite::Error> {
+    let mut stmt = conn.prepare("
+        INSERT INTO params (parameter, value) VALUES (:parameter, :value)
+        ON CONFLICT(parameter) DO UPDATE SET value = excluded.value
+    ")?;
+    stmt.bind::<&[(_, sqlite::Value)]>(&[
+        (":parameter", SCHEMA_VERSION_PARAM.into()),
+        (":value", version.to_string().into()),
+    ][..])?;
+    stmt.next()?;
+    Ok(())
+}
+
+fn backup(conn: &sqlite::Connection, path: &Path) -> Result<(), anyhow::Error> {
+    // A backup left by an earlier, failed attempt already holds this version
+    if path.exists() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare("VACUUM INTO ?")?;
+    stmt.bind((1, path.to_string_lossy().as_ref()))?;
+    stmt.next()?;
+    Ok(())
+}
+
+fn table_exists(conn: &sqlite::Connection, table: &str) -> Result<bool, anyhow::Error> {
+    let mut stmt = conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?;
+    stmt.bind((1, table))?;
+    Ok(stmt.next()? == sqlite::State::Row)
+}
+
+fn column_exists(conn: &sqlite::Connection, table: &str, column: &str) -> Result<bool, anyhow::Error> {
+    let mut stmt = conn.prepare("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")?;
+    stmt.bind((1, table))?;
+    stmt.bind((2, column))?;
+    Ok(stmt.next()? == sqlite::State::Row)
+}
@@ -0,0 +1,31 @@
+use crate::{IO, PromptArgs, Timesheet, sequence::prev_entry, storage::Record};
+
+/// The placeholders a prompt template can use, for `timest prompt --help`.
+pub const PLACEHOLDERS: &str = "{state} in/out, {elapsed} H:MM since the last entry, {since} HH:MM of the last entry, {project}, {tag}";
+
+/// Prints the clock state for a shell prompt. Only reads the last entry and
+/// never asks a time server, so it is cheap enough to run on every prompt.
+pub fn prompt_cmd(timesheet: &Timesheet, args: PromptArgs) -> Result<(), anyhow::Error> {
+    let now = chrono::Local::now().naive_local();
+
+    let Some(Record { timestamp, io, project, tag, .. }) = prev_entry(timesheet.storage(), now, None)? else {
+        println!("{}", args.clocked_out.unwrap_or_default());
+        return Ok(());
+    };
+    let project = project.unwrap_or_default();
+    let tag = tag.unwrap_or_default();
+
+    let template = match io {
+        IO::I => args.template,
+        IO::O => args.clocked_out.unwrap_or(args.template),
+    };
+    let minutes = (now - timestamp).num_minutes();
+    let output = template
+        .replace("{state}", io.as_word())
+        .replace("{elapsed}", &format!("{}:{:02}", minutes / 60, minutes % 60))
+        .replace("{since}", &timestamp.format("%H:%M").to_string())
+        .replace("{project}", &project)
+        .replace("{tag}", &tag);
+    println!("{output}");
+    Ok(())
+}
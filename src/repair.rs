@@ -0,0 +1,161 @@
+use chrono::NaiveDate;
+use std::collections::HashSet;
+
+use crate::{Error, IO, RepairArgs, Timesheet, check_time::ask, sequence::sequence_problems};
+use crate::report::interval::{IntervalStatus, get_paired_intervals};
+use crate::storage::{NewRecord, Record, RecordUpdate, Storage};
+use crate::time_expr::{TimeExpr, amend_datetime};
+
+/// A way to repair a broken interval.
+enum Repair {
+    /// Add the missing entry, at a time the user gives
+    Add { io: IO, after: Option<Record>, before: Option<Record> },
+    /// Turn an entry into the opposite kind
+    Flip(Record),
+    /// Delete an entry that doubles up another
+    Delete(Record),
+}
+
+impl Repair {
+    fn describe(&self) -> String {
+        match self {
+            Repair::Add { io, .. } => format!("Add a clock {}", io.as_word()),
+            Repair::Flip(record) => format!(
+                "Change entry {} at {} from a clock {} to a clock {}",
+                record.id, record.timestamp.time(), record.io.as_word(), opposite(record.io).as_word()
+            ),
+            Repair::Delete(record) => format!(
+                "Delete entry {}, the extra clock {} at {}",
+                record.id, record.io.as_word(), record.timestamp.time()
+            ),
+        }
+    }
+}
+
+/// Ids of the entries on either side of an interval.
+fn ids(records: &[Option<Record>; 2]) -> [Option<i64>; 2] {
+    [0, 1].map(|index| records[index].as_ref().map(|record| record.id))
+}
+
+fn opposite(io: IO) -> IO {
+    match io {
+        IO::I => IO::O,
+        IO::O => IO::I,
+    }
+}
+
+/// Walks through each incomplete interval on a day, offering likely repairs.
+pub fn repair_cmd(timesheet: &Timesheet, args: RepairArgs) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let no_input = timesheet.config().no_input;
+    let today = chrono::Local::now().date_naive();
+    let date = args.date.map_or(Ok(today), |date| date.resolve(today))?;
+
+    // Intervals the user chose to leave alone, by the ids of their entries
+    let mut skipped: HashSet<[Option<i64>; 2]> = HashSet::new();
+    loop {
+        let broken = get_paired_intervals(storage, date, date)?
+            .into_iter()
+            .filter(|(interval, _)| interval.status != IntervalStatus::Complete)
+            // Still clocked in, which isn't broken yet
+            .filter(|(interval, [_, after])| !(interval.status == IntervalStatus::MissingEnd && after.is_none() && date == today))
+            .find(|(_, records)| !skipped.contains(&ids(records)));
+        let Some((interval, [before, after])) = broken else {
+            break;
+        };
+
+        let (problem, repairs) = match interval.status {
+            IntervalStatus::MissingEnd => {
+                let clock_in = before.clone().expect("Missing ends come after a clock in");
+                let mut repairs = vec![Repair::Add { io: IO::O, after: before.clone(), before: after.clone() }];
+                if let Some(next) = after.clone() {
+                    repairs.push(Repair::Flip(next.clone()));
+                    repairs.push(Repair::Delete(next));
+                }
+                (format!("the clock in at {} has no clock out", clock_in.timestamp), repairs)
+            },
+            IntervalStatus::MissingStart => {
+                let clock_out = after.clone().expect("Missing starts come before a clock out");
+                let mut repairs = vec![Repair::Add { io: IO::I, after: before.clone(), before: after.clone() }];
+                match before.clone() {
+                    // The clock out before was probably meant to be a clock in,
+                    // as long as that doesn't leave the one before it open
+                    Some(prev) => {
+                        if sequence_problems(storage, prev.timestamp, IO::I, Some((prev.id, prev.timestamp)))?.is_empty() {
+                            repairs.push(Repair::Flip(prev));
+                        }
+                        repairs.push(Repair::Delete(clock_out.clone()));
+                    },
+                    None => repairs.push(Repair::Flip(clock_out.clone())),
+                }
+                (format!("the clock out at {} has no clock in", clock_out.timestamp), repairs)
+            },
+            IntervalStatus::Complete => unreachable!("Only incomplete intervals are repaired"),
+        };
+
+        println!("Broken interval on {date}: {problem}");
+        for (number, repair) in repairs.iter().enumerate() {
+            println!("  {}) {}", number + 1, repair.describe());
+        }
+        println!("  s) Skip");
+        println!("  q) Quit");
+
+        let answer = ask("Choose:", no_input).ok_or(Error::UserAborted)?;
+        match answer.as_str() {
+            "s" | "S" => {
+                skipped.insert(ids(&[before, after]));
+            },
+            "q" | "Q" => return Ok(()),
+            choice => match choice.parse::<usize>().ok().and_then(|number| repairs.get(number.wrapping_sub(1))) {
+                Some(repair) => apply(storage, date, repair, no_input)?,
+                None => println!("`{choice}` is not one of the choices"),
+            },
+        }
+    }
+
+    println!("Nothing left to repair on {date}");
+    Ok(())
+}
+
+fn apply(storage: &dyn Storage, date: NaiveDate, repair: &Repair, no_input: bool) -> Result<(), anyhow::Error> {
+    match repair {
+        Repair::Add { io, after, before } => {
+            let example = match (after, before) {
+                (_, Some(before)) => before.timestamp.time(),
+                (Some(after), None) => after.timestamp.time(),
+                (None, None) => chrono::NaiveTime::MIN,
+            };
+            let Some(answer) = ask(&format!("Clock {} at what time? (e.g. {example})", io.as_word()), no_input) else {
+                return Err(Error::UserAborted.into());
+            };
+            let time = match answer.parse::<TimeExpr>() {
+                Ok(time) => time,
+                Err(err) => {
+                    println!("{err}");
+                    return Ok(());
+                },
+            };
+            let now = chrono::Local::now().naive_local();
+            let datetime = amend_datetime(Some(time), None, date.and_time(chrono::NaiveTime::MIN), now)?;
+
+            let out_of_place = after.as_ref().is_some_and(|after| datetime <= after.timestamp)
+                || before.as_ref().is_some_and(|before| datetime >= before.timestamp);
+            let problems = sequence_problems(storage, datetime, *io, None)?;
+            if out_of_place || !problems.is_empty() {
+                println!("A clock {} at {datetime} doesn't fit here. Nothing was saved", io.as_word());
+                return Ok(());
+            }
+            let id = storage.insert(NewRecord { timestamp: datetime, io: *io, project: None, tag: None, note: None, correction: None })?;
+            println!("added: {}", storage.get(id)?.expect("The entry was just added"));
+        },
+        Repair::Flip(record) => {
+            storage.update(record.id, RecordUpdate { io: Some(opposite(record.io)), ..Default::default() })?;
+            println!("changed: {}", storage.get(record.id)?.expect("The entry was just changed"));
+        },
+        Repair::Delete(record) => {
+            storage.delete(record.id)?;
+            println!("deleted: {record}");
+        },
+    }
+    Ok(())
+}
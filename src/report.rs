@@ -1,65 +1,113 @@
-use crate::{ReportArgs, ReportStyle};
+use crate::{ReportArgs, Timesheet, ReportFormat, ReportStyle};
+use chrono::{Datelike, Duration, NaiveDate};
 
 use simple_report::simple_report;
 
+pub mod interval;
+
 mod fancy_report;
 use fancy_report::fancy_report;
 
+mod range_report;
+use range_report::range_report;
+
+mod export;
+use export::export;
+
+mod status;
+pub use status::status_cmd;
 
-pub fn report_cmd(conn: sqlite::Connection, args: ReportArgs) {
+
+pub fn report_cmd(timesheet: &Timesheet, args: ReportArgs) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    let config = timesheet.config();
     let report_style = args.report_style.unwrap_or(ReportStyle::Fancy);
+    let rule = args.overnight.unwrap_or(config.overnight);
+    let today = chrono::Local::now().date_naive();
     let date = if args.yesterday {
-        chrono::Local::now().date_naive().pred_opt().expect("People should not be clocking in at NaiveDate::MIN")
+        today.pred_opt().expect("People should not be clocking in at NaiveDate::MIN")
     } else {
-        args.date.unwrap_or(chrono::Local::now().date_naive())
+        args.date.map_or(Ok(today), |date| date.resolve(today))?
+    };
+
+    let format = args.format.unwrap_or(ReportFormat::Text);
+    let range = date_range(&args, date, today)?;
+
+    if let Some((from, to)) = range {
+        if from > to {
+            return Err(anyhow::anyhow!("--from {from} is after --to {to}"));
+        }
+    }
+
+    if format != ReportFormat::Text {
+        let (from, to) = range.unwrap_or((date, date));
+        return export(storage, from, to, rule, format);
+    }
+
+    let Some((from, to)) = range else {
+        match report_style {
+            ReportStyle::Simple => simple_report(storage, date)?,
+            ReportStyle::Fancy => fancy_report(storage, date, rule)?,
+        }
+        return Ok(());
     };
+
     match report_style {
-        ReportStyle::Simple => simple_report(&conn, date).unwrap(),
-        ReportStyle::Fancy => fancy_report(&conn, date).unwrap(),
+        ReportStyle::Simple => for date in from.iter_days().take_while(|date| date <= &to) {
+            simple_report(storage, date)?;
+        },
+        ReportStyle::Fancy => range_report(storage, from, to, rule)?,
     }
+    Ok(())
 }
 
-const SQL_TODAYS_CLOCK: &str = "
-    SELECT * FROM times
-    WHERE Date(timestamp) = :date
-    ORDER BY timestamp
-";
+/// First and last day (inclusive) of a multi-day report, or `None` if only a
+/// single day was requested.
+fn date_range(args: &ReportArgs, date: NaiveDate, today: NaiveDate) -> Result<Option<(NaiveDate, NaiveDate)>, anyhow::Error> {
+    if args.week {
+        let too_far = || anyhow::anyhow!("the week containing {date} is too far away to report on");
+        let monday = date.checked_sub_signed(Duration::days(date.weekday().num_days_from_monday() as i64))
+            .ok_or_else(too_far)?;
+        let sunday = monday.checked_add_signed(Duration::days(6)).ok_or_else(too_far)?;
+        Ok(Some((monday, sunday)))
+    } else if args.month {
+        let first = date.with_day(1).expect("Every month has a first day");
+        let last = if date.month() == 12 {
+            NaiveDate::from_ymd_opt(date.year(), 12, 31)
+        } else {
+            NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1).and_then(|next_first| next_first.pred_opt())
+        }.expect("Every month has a last day");
+        Ok(Some((first, last)))
+    } else {
+        let Some(from) = args.from else {
+            return Ok(None);
+        };
+        let to = args.to.map_or(Ok(today), |to| to.resolve(today))?;
+        Ok(Some((from.resolve(today)?, to)))
+    }
+}
 
 mod simple_report {
-    use chrono::{NaiveDate, NaiveDateTime};
-    pub fn simple_report(conn: &sqlite::Connection, date: NaiveDate) -> Result<(), anyhow::Error> {
-        let date = date.format("%Y-%m-%d").to_string();
-        
+    use chrono::NaiveDate;
+    use crate::storage::Storage;
+    use super::fancy_report::truncate;
+
+    /// Longer notes are cut short
+    const NOTE_WIDTH: usize = 40;
+
+    pub fn simple_report(storage: &dyn Storage, date: NaiveDate) -> Result<(), anyhow::Error> {
         println!("Gathering data from day {date}");
-        let mut stmt = conn.prepare(super::SQL_TODAYS_CLOCK)?;
-        stmt.bind((1, date.as_str()))?;
+        let records = storage.range(date, date)?;
         println!("====TODAY'S TIMESHEET====");
-        println!("->>    {}", &sql_date(conn)?);
+        println!("->>    {}", chrono::Utc::now().date_naive());
         println!(" ________________________");
 
-        for row in stmt.iter() {
-            if let Ok(mut result) = row {
-                let idx: i64 = result.read(0);
-                let timestamp: String = result.take(1).try_into().unwrap();
-                let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S").unwrap();
-                let io: String = result.take(2).try_into().unwrap();
-                println!("|  {idx}  |  {}  |  {io}  |",timestamp.time());
-            } else {
-                println!("|   |  Bad row 💀");
+        for record in records {
+            match &record.note {
+                Some(note) => println!("|  {}  |  {}  |  {}  |  {}", record.id, record.timestamp.time(), record.io, truncate(note, NOTE_WIDTH)),
+                None => println!("|  {}  |  {}  |  {}  |", record.id, record.timestamp.time(), record.io),
             }
         }
         Ok(())
     }
-
-    fn sql_date(conn: &sqlite::Connection) -> Result<NaiveDate, anyhow::Error> {
-        let date: String = conn.prepare("SELECT DATE()")?
-            .iter()
-            .next()
-            .expect("Expect at least 1 row")?
-            .take(0)
-            .try_into()?;
-        let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?;
-        Ok(date)
-    }
 }
-
@@ -0,0 +1,158 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use crate::{IO, OvernightRule, ReportFormat};
+
+use super::interval::*;
+
+/// Top level of `--format json`. Fields are only ever added, never renamed.
+#[derive(Serialize)]
+struct Report<'a> {
+    from: NaiveDate,
+    to: NaiveDate,
+    overnight: OvernightRule,
+    days: Vec<Day<'a>>,
+    /// Seconds worked on all complete days
+    total_seconds: i64,
+    /// Whether every day in the report is complete
+    complete: bool,
+}
+
+#[derive(Serialize)]
+struct Day<'a> {
+    date: NaiveDate,
+    records: Vec<&'a Record>,
+    intervals: Vec<IntervalRow>,
+    /// `null` if the day has incomplete intervals
+    seconds_worked: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct IntervalRow {
+    /// `null` for missing_start intervals
+    start: Option<NaiveDateTime>,
+    /// `null` for missing_end intervals
+    end: Option<NaiveDateTime>,
+    status: IntervalStatus,
+    /// `null` unless the interval is complete
+    seconds: Option<i64>,
+    project: Option<String>,
+    tag: Option<String>,
+    note: Option<String>,
+}
+
+impl From<&Interval> for IntervalRow {
+    fn from(interval: &Interval) -> Self {
+        Self {
+            start: (interval.status != IntervalStatus::MissingStart).then_some(interval.start),
+            end: (interval.status != IntervalStatus::MissingEnd).then_some(interval.end),
+            status: interval.status.clone(),
+            seconds: (interval.status == IntervalStatus::Complete).then(|| interval.duration()),
+            project: interval.project.clone(),
+            tag: interval.tag.clone(),
+            note: interval.note.clone(),
+        }
+    }
+}
+
+/// One line of `--format csv`. The `type` column says which of the other
+/// columns are filled in: `record`, `interval`, `day` or `total`.
+#[derive(Serialize, Default)]
+struct CsvRow {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    date: Option<NaiveDate>,
+    id: Option<i64>,
+    io: Option<IO>,
+    timestamp: Option<NaiveDateTime>,
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    status: Option<IntervalStatus>,
+    seconds: Option<i64>,
+    project: Option<String>,
+    tag: Option<String>,
+}
+
+pub fn export(storage: &dyn Storage, from: NaiveDate, to: NaiveDate, rule: OvernightRule, format: ReportFormat) -> Result<(), anyhow::Error> {
+    let records = get_records(storage, from, to)?;
+    let intervals = get_intervals(storage, from, to)?;
+
+    let days: Vec<Day> = from.iter_days()
+        .take_while(|date| date <= &to)
+        .map(|date| {
+            let intervals = intervals_for_day(&intervals, date, rule);
+            Day {
+                date,
+                records: records.iter()
+                    .filter(|record| record.timestamp.date() == date)
+                    .collect(),
+                seconds_worked: seconds_worked(&intervals),
+                intervals: intervals.iter().map(IntervalRow::from).collect(),
+            }
+        })
+        .collect();
+
+    let report = Report {
+        from,
+        to,
+        overnight: rule,
+        total_seconds: days.iter().filter_map(|day| day.seconds_worked).sum(),
+        complete: days.iter().all(|day| day.seconds_worked.is_some()),
+        days,
+    };
+
+    match format {
+        ReportFormat::Json => print_json(&report),
+        ReportFormat::Csv => print_csv(&report),
+        ReportFormat::Text => unreachable!("Text reports are not exported"),
+    }
+}
+
+fn print_json(report: &Report) -> Result<(), anyhow::Error> {
+    serde_json::to_writer_pretty(std::io::stdout(), report)?;
+    println!();
+    Ok(())
+}
+
+fn print_csv(report: &Report) -> Result<(), anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for day in report.days.iter() {
+        for record in day.records.iter() {
+            writer.serialize(CsvRow {
+                kind: "record",
+                date: Some(day.date),
+                id: Some(record.id),
+                io: Some(record.io),
+                timestamp: Some(record.timestamp),
+                project: record.project.clone(),
+                tag: record.tag.clone(),
+                ..Default::default()
+            })?;
+        }
+        for interval in day.intervals.iter() {
+            writer.serialize(CsvRow {
+                kind: "interval",
+                date: Some(day.date),
+                start: interval.start,
+                end: interval.end,
+                status: Some(interval.status.clone()),
+                seconds: interval.seconds,
+                project: interval.project.clone(),
+                tag: interval.tag.clone(),
+                ..Default::default()
+            })?;
+        }
+        writer.serialize(CsvRow {
+            kind: "day",
+            date: Some(day.date),
+            seconds: day.seconds_worked,
+            ..Default::default()
+        })?;
+    }
+    writer.serialize(CsvRow {
+        kind: "total",
+        seconds: Some(report.total_seconds),
+        ..Default::default()
+    })?;
+    writer.flush()?;
+    Ok(())
+}
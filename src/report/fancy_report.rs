@@ -1,188 +1,131 @@
 use chrono::{NaiveTime, NaiveDate, NaiveDateTime};
-use sqlite::State;
-use crate::IO;
+use crate::OvernightRule;
 use recolored::Colorize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct Interval {
-        start: NaiveTime,
-        end: NaiveTime,
-        status: IntervalStatus,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum IntervalStatus {
-    Complete,
-    MissingStart,
-    MissingEnd
-}
-
-impl Interval {
-    pub fn from_records(prev_record: &Option<Record>, current_record: &Option<Record>) -> Option<Self> {
-        match (prev_record, current_record) {
-            // No complete interval (invalid or otherwise)
-            (None, None)                                     | 
-            (None, Some(Record(_, IO::I)))                   |
-            (Some(Record(_, IO::O)), None)                   |
-            (Some(Record(_, IO::O)), Some(Record(_, IO::I))) => None,
-            
-            // Invalid interval: missing start time
-            (None, Some(Record(time, IO::O))) => Some(
-                Self { 
-                    start: NaiveTime::MIN, 
-                    end: *time,
-                    status: IntervalStatus::MissingStart
-                }),
-            (Some(Record(time_a, IO::O)), Some(Record(time_b, IO::O))) => Some(
-                Self { 
-                    start: *time_a,
-                    end: *time_b,
-                    status: IntervalStatus::MissingStart
-                }),
-
-            // Invalid interval: missing end time
-            (Some(Record(time, IO::I)), None) => Some(
-                Self { 
-                    start: *time,
-                    end: NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
-                    status: IntervalStatus::MissingEnd
-                }),
-            (Some(Record(time_a, IO::I)), Some(Record(time_b, IO::I))) => Some(
-                Self { 
-                    start: *time_a,
-                    end: *time_b,
-                    status: IntervalStatus::MissingEnd
-                }),
-
-            // Complete interval
-            (Some(Record(time_in, IO::I)), Some(Record(time_out, IO::O))) => Some(
-                Self { 
-                    start: *time_in, 
-                    end: *time_out,
-                    status: IntervalStatus::Complete
-                }),
-        }
-    }
-
-    pub fn contains(&self, time: &NaiveTime) -> bool {
-        time > &self.start && time < &self.end
-    }
-
-    pub fn duration(&self) -> i64 {
-        self.end.signed_duration_since(self.start).num_seconds()
-    }
-}
-
-#[derive(Debug, Clone)]
-struct Record(NaiveTime, IO);
-
-impl Record {
-    pub fn from_statement(stmt: &sqlite::Statement) -> Result<Self, anyhow::Error> {
-        let timestamp: String = stmt.read::<String, _>(1)?;
-        let time = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")?
-            .time();
-        let operation  = IO::try_from(
-            stmt.read::<String, _>(2)?
-            .as_str()
-        )?;
-        Ok(Self(time, operation))
-    }
-}
-
-pub fn fancy_report(conn: &sqlite::Connection, date: NaiveDate) -> Result<(), anyhow::Error> {
-    let mut stmt = conn.prepare(super::SQL_TODAYS_CLOCK)?;
+use super::interval::*;
 
-    let date = date.format("%Y-%m-%d").to_string();
-    stmt.bind((1, date.as_str()))?;
-
-    let intervals = get_intervals(&mut stmt)?;
-    generate_report(&intervals);
+pub fn fancy_report(storage: &dyn Storage, date: NaiveDate, rule: OvernightRule) -> Result<(), anyhow::Error> {
+    let intervals = get_intervals(storage, date, date)?;
+    let intervals = intervals_for_day(&intervals, date, rule);
+    generate_report(date, &intervals);
 
     Ok(())
 }
 
-fn get_intervals(stmt: &mut sqlite::Statement) -> Result<Vec<Interval>, anyhow::Error> {
-    let mut intervals: Vec<Interval> = vec![];
-    let mut prev_record: Option<Record> = None;
-
-    while let State::Row = stmt.next()? {
-        let current_record = Some(Record::from_statement(stmt)?);
-        if let Some(interval) = Interval::from_records(&prev_record, &current_record) {
-            intervals.push(interval)
-        }
-        prev_record = current_record;
-    }
-
-    // Parse last record
-    if let Some(interval) = Interval::from_records(&prev_record, &None) {
-        intervals.push(interval)
-    }
-
-    Ok(intervals)
-}
-
-fn generate_report(intervals: &[Interval]) {
+fn generate_report(date: NaiveDate, intervals: &[Interval]) {
     print_header();
-    print_bar(intervals);
-    print_summary(intervals);
+    print_bar(date, intervals);
+    print_summary(date, intervals);
+    print_breakdown(intervals);
     print_total(intervals);
 }
 
 fn print_total(intervals: &[Interval]) {
     if let Some(duration) = seconds_worked(intervals) {
         let notice = "TOTAL TIME WORKED: ".blue();
-        let time_worked = NaiveTime::from_num_seconds_from_midnight_opt(duration as u32, 0)
-            .unwrap()
-            .to_string();
-        let pretty_time = if let Some(stripped) = time_worked.strip_prefix('0') {
-            stripped
-        } else {
-            time_worked.as_str()
-        }.blue().bold();
+        let pretty_time = format_total(duration).blue().bold();
         println!("{}{}", notice, pretty_time);
     } else {
         print!("{}", "ERROR".red().bold());
         println!(" {}", "there are some incomplete intervals".red());
         println!("\tPlease run `timest report simple` to find the missing records ");
-        println!("\tand fix them with `timest clock` and `timest fix`");
+        println!("\tand fix them with `timest clock` and `timest fix`, or run `timest repair`");
     }
 }
 
-fn seconds_worked(intervals: &[Interval]) -> Option<i64> {
-    let mut total = 0_i64;
-    for interval in intervals.iter() {
-        if interval.status == IntervalStatus::Complete {
-            total += interval.duration();
-        } else {
-            return None;
-        }
+/// Formats a number of seconds as HH:MM:SS. Hours are not wrapped at 24, so
+/// this also works for totals spanning several days.
+pub(super) fn format_seconds(seconds: i64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// Like [`format_seconds`], without the leading zero.
+pub(super) fn format_total(seconds: i64) -> String {
+    let time_worked = format_seconds(seconds);
+    match time_worked.strip_prefix('0') {
+        Some(stripped) => stripped.to_owned(),
+        None => time_worked,
+    }
+}
+
+/// `text` cut down to `width` characters, ending in an ellipsis if it was cut.
+pub(super) fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Formats a time as seen from `date`. The end of a shift clipped at midnight
+/// is shown as 24:00:00.
+fn format_time(datetime: &NaiveDateTime, date: NaiveDate) -> String {
+    if *datetime == start_of_next_day(date) {
+        "24:00:00".to_owned()
+    } else {
+        datetime.time().to_string()
     }
-    Some(total)
 }
 
-fn print_summary(intervals: &[Interval]) {
+/// Width of the NOTE column of the summary. Longer notes are cut short.
+const NOTE_WIDTH: usize = 30;
+
+fn print_summary(date: NaiveDate, intervals: &[Interval]) {
+    // Notes get a column of their own, on days that have any
+    let with_notes = intervals.iter().any(|interval| interval.note.is_some());
+    let border = |left: &str, joint: &str, right: &str| {
+        let note = if with_notes { format!("{joint}{}", "─".repeat(NOTE_WIDTH + 2)) } else { String::new() };
+        println!("{left}───────────────────────{joint}────────────{note}{right}");
+    };
+    let row = |interval: &str, duration: &str, note: &str| {
+        let note = if with_notes { format!(" {:<NOTE_WIDTH$} │", truncate(note, NOTE_WIDTH)) } else { String::new() };
+        println!("│{interval}│{duration}│{note}");
+    };
+
     println!("            SUMMARY");
-    println!("┌───────────────────────┬────────────┐");
-    println!("│        INTERVAL       │  DURATION  │");
-    println!("├───────────────────────┼────────────┤");
+    border("┌", "┬", "┐");
+    row("        INTERVAL       ", "  DURATION  ", "NOTE");
+    border("├", "┼", "┤");
     for interval in intervals.iter() {
-        match interval.status {
-            IntervalStatus::Complete => println!(
-                "│  {} - {}  │  {}  │", 
-                interval.start, 
-                interval.end, 
-                NaiveTime::from_num_seconds_from_midnight_opt(
-                    interval.duration() as u32, 0
-                ).unwrap()),
-            IntervalStatus::MissingEnd => println!(
-                "│  {} - ??:??:??  │            │ ",
-                interval.start
-            ),
-            IntervalStatus::MissingStart => println!(
-                "│  ??:??:?? - {}  │            │", 
-                interval.end
-            ),
-        }
+        let start = match interval.status {
+            IntervalStatus::MissingStart => "??:??:??".to_owned(),
+            _ => format_time(&interval.start, date),
+        };
+        let end = match interval.status {
+            IntervalStatus::MissingEnd => "??:??:??".to_owned(),
+            _ => format_time(&interval.end, date),
+        };
+        let duration = match interval.status {
+            IntervalStatus::Complete => format_seconds(interval.duration()),
+            _ => String::new(),
+        };
+        row(&format!("  {start} - {end}  "), &format!("  {duration:<8}  "), interval.note.as_deref().unwrap_or(""));
+    }
+    border("└", "┴", "┘");
+}
+
+/// Time per project and per tag, for days where any were given.
+fn print_breakdown(intervals: &[Interval]) {
+    if intervals.iter().all(|interval| interval.project.is_none() && interval.tag.is_none()) {
+        return;
+    }
+    print_breakdown_table("BY PROJECT", intervals, |interval| &interval.project);
+    print_breakdown_table("  BY TAG", intervals, |interval| &interval.tag);
+}
+
+fn print_breakdown_table(title: &str, intervals: &[Interval], label: fn(&Interval) -> &Option<String>) {
+    let mut totals: BTreeMap<Option<&str>, i64> = BTreeMap::new();
+    for interval in intervals.iter().filter(|interval| interval.status == IntervalStatus::Complete) {
+        *totals.entry(label(interval).as_deref()).or_default() += interval.duration();
+    }
+
+    println!("          {title}");
+    println!("┌───────────────────────┬────────────┐");
+    for (name, seconds) in totals.iter() {
+        let name: String = name.unwrap_or("(none)").chars().take(21).collect();
+        println!("│ {:<21} │  {}  │", name, format_seconds(*seconds));
     }
     println!("└───────────────────────┴────────────┘");
 }
@@ -198,7 +141,7 @@ fn print_header() {
     println!("         6");
 }
 
-fn print_bar(intervals: &[Interval]) {
+fn print_bar(date: NaiveDate, intervals: &[Interval]) {
     // Each dash is a 15 minute interval
     'outer: for i in 0..(24 * 4) {
         if i % 4 == 0 {
@@ -206,8 +149,9 @@ fn print_bar(intervals: &[Interval]) {
         }
         for interval in intervals.iter() {
             let num_seconds = i * 15 * 60 + 15;
-            let time = NaiveTime::from_num_seconds_from_midnight_opt(num_seconds, 0)
-                .unwrap();
+            let time = date.and_time(
+                NaiveTime::from_num_seconds_from_midnight_opt(num_seconds, 0).unwrap()
+            );
             if interval.contains(&time) {
                 match interval.status {
                     IntervalStatus::Complete => print!("{}", "+".blue()),
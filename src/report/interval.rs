@@ -0,0 +1,279 @@
+use chrono::{NaiveTime, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use crate::{IO, OvernightRule};
+pub use crate::storage::{Record, Storage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval {
+        pub start: NaiveDateTime,
+        pub end: NaiveDateTime,
+        pub status: IntervalStatus,
+        pub project: Option<String>,
+        pub tag: Option<String>,
+        pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntervalStatus {
+    Complete,
+    MissingStart,
+    MissingEnd
+}
+
+impl Interval {
+    pub fn from_records(prev_record: &Option<Record>, current_record: &Option<Record>) -> Option<Self> {
+        let as_pair = |record: &Option<Record>| record.as_ref().map(|record| (record.timestamp, record.io));
+        let (start, end, status) = match (as_pair(prev_record), as_pair(current_record)) {
+            // No complete interval (invalid or otherwise)
+            (None, None)                                     |
+            (None, Some((_, IO::I)))                         |
+            (Some((_, IO::O)), None)                         |
+            (Some((_, IO::O)), Some((_, IO::I))) => return None,
+
+            // Invalid interval: missing start time
+            (None, Some((time, IO::O))) => (
+                start_of_day(time.date()),
+                time,
+                IntervalStatus::MissingStart
+            ),
+            (Some((time_a, IO::O)), Some((time_b, IO::O))) => (
+                // Don't let a missing clock in reach back into previous days
+                if time_a.date() == time_b.date() {
+                    time_a
+                } else {
+                    start_of_day(time_b.date())
+                },
+                time_b,
+                IntervalStatus::MissingStart
+            ),
+
+            // Invalid interval: missing end time
+            (Some((time, IO::I)), None) => (
+                time,
+                end_of_day(time.date()),
+                IntervalStatus::MissingEnd
+            ),
+            (Some((time_a, IO::I)), Some((time_b, IO::I))) => (
+                time_a,
+                // Don't let a missing clock out run on into following days
+                if time_a.date() == time_b.date() {
+                    time_b
+                } else {
+                    end_of_day(time_a.date())
+                },
+                IntervalStatus::MissingEnd
+            ),
+
+            // Complete interval, possibly crossing midnight
+            (Some((time_in, IO::I)), Some((time_out, IO::O))) => (
+                time_in,
+                time_out,
+                IntervalStatus::Complete
+            ),
+        };
+
+        // Labels come from the clock in, falling back on the clock out
+        let (first, second) = match status {
+            IntervalStatus::MissingStart => (current_record, &None),
+            IntervalStatus::MissingEnd => (prev_record, &None),
+            IntervalStatus::Complete => (prev_record, current_record),
+        };
+        let labels = |record: &Option<Record>| record.as_ref()
+            .map(|record| (record.project.clone(), record.tag.clone(), record.note.clone()))
+            .unwrap_or_default();
+        let (first_project, first_tag, first_note) = labels(first);
+        let (second_project, second_tag, second_note) = labels(second);
+
+        Some(Self {
+            start,
+            end,
+            status,
+            project: first_project.or(second_project),
+            tag: first_tag.or(second_tag),
+            note: first_note.or(second_note),
+        })
+    }
+
+    pub fn contains(&self, time: &NaiveDateTime) -> bool {
+        time > &self.start && time < &self.end
+    }
+
+    pub fn duration(&self) -> i64 {
+        self.end.signed_duration_since(self.start).num_seconds()
+    }
+
+    /// The part of this interval that falls on `date`, if any.
+    fn clip_to_day(&self, date: NaiveDate) -> Option<Self> {
+        let day_start = start_of_day(date);
+        let day_end = start_of_next_day(date);
+        let overlaps = self.start < day_end && (self.end > day_start || self.start >= day_start);
+        overlaps.then(|| Self {
+            start: self.start.max(day_start),
+            end: self.end.min(day_end),
+            ..self.clone()
+        })
+    }
+}
+
+/// Raw records from `from` through `to`.
+pub fn get_records(storage: &dyn Storage, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>, anyhow::Error> {
+    storage.range(from, to)
+}
+
+/// All intervals touching the days `from` through `to`, unclipped.
+pub fn get_intervals(storage: &dyn Storage, from: NaiveDate, to: NaiveDate) -> Result<Vec<Interval>, anyhow::Error> {
+    Ok(get_paired_intervals(storage, from, to)?
+        .into_iter()
+        .map(|(interval, _)| interval)
+        .collect())
+}
+
+/// An interval and the records it was made from: the one before it (if any)
+/// and the one after it (if any).
+pub type PairedInterval = (Interval, [Option<Record>; 2]);
+
+/// Like [`get_intervals`], along with the records each interval was made from.
+pub fn get_paired_intervals(storage: &dyn Storage, from: NaiveDate, to: NaiveDate) -> Result<Vec<PairedInterval>, anyhow::Error> {
+    // The closest record on either side pairs up shifts crossing into or out of the range
+    let before = match from.pred_opt() {
+        Some(day_before) => storage.last_until(end_of_day(day_before), None)?,
+        None => None,
+    };
+    let records = before.into_iter()
+        .chain(storage.range(from, to)?)
+        .chain(storage.first_after(end_of_day(to), None)?);
+
+    let mut intervals = vec![];
+    let mut prev_record: Option<Record> = None;
+
+    for record in records {
+        let current_record = Some(record);
+        if let Some(interval) = Interval::from_records(&prev_record, &current_record) {
+            intervals.push((interval, [prev_record.clone(), current_record.clone()]))
+        }
+        prev_record = current_record;
+    }
+
+    // Parse last record
+    if let Some(interval) = Interval::from_records(&prev_record, &None) {
+        intervals.push((interval, [prev_record, None]))
+    }
+
+    let range_start = start_of_day(from);
+    let range_end = start_of_next_day(to);
+    intervals.retain(|(interval, _)| interval.start < range_end && interval.end >= range_start);
+
+    Ok(intervals)
+}
+
+/// The intervals counted towards `date`. Intervals that cross midnight are
+/// either split at midnight or attributed whole to the day they start or end on.
+pub fn intervals_for_day(intervals: &[Interval], date: NaiveDate, rule: OvernightRule) -> Vec<Interval> {
+    intervals.iter()
+        .filter_map(|interval| match rule {
+            OvernightRule::Split => interval.clip_to_day(date),
+            OvernightRule::Start => (interval.start.date() == date).then(|| interval.clone()),
+            OvernightRule::End => (interval.end.date() == date).then(|| interval.clone()),
+        })
+        .collect()
+}
+
+pub fn seconds_worked(intervals: &[Interval]) -> Option<i64> {
+    let mut total = 0_i64;
+    for interval in intervals.iter() {
+        if interval.status == IntervalStatus::Complete {
+            total += interval.duration();
+        } else {
+            return None;
+        }
+    }
+    Some(total)
+}
+
+pub fn start_of_day(date: NaiveDate) -> NaiveDateTime {
+    date.and_time(NaiveTime::MIN)
+}
+
+/// Midnight at the end of `date`, or the last moment there is if `date` is
+/// the last day.
+pub fn start_of_next_day(date: NaiveDate) -> NaiveDateTime {
+    date.succ_opt().map_or(NaiveDateTime::MAX, start_of_day)
+}
+
+fn end_of_day(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(23, 59, 59).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MemoryStorage, NewRecord};
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 5, day).unwrap()
+    }
+
+    fn at(date: u32, hour: u32) -> NaiveDateTime {
+        day(date).and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn storage(entries: &[(NaiveDateTime, IO)]) -> MemoryStorage {
+        let storage = MemoryStorage::default();
+        for &(timestamp, io) in entries {
+            storage.insert(NewRecord { timestamp, io, project: None, tag: None, note: None, correction: None }).unwrap();
+        }
+        storage
+    }
+
+    fn spans(intervals: &[Interval]) -> Vec<(NaiveDateTime, NaiveDateTime, IntervalStatus)> {
+        intervals.iter().map(|interval| (interval.start, interval.end, interval.status.clone())).collect()
+    }
+
+    #[test]
+    fn pairs_ins_and_outs() {
+        let storage = storage(&[(at(31, 8), IO::I), (at(31, 12), IO::O), (at(31, 13), IO::I), (at(31, 17), IO::O)]);
+        let intervals = get_intervals(&storage, day(31), day(31)).unwrap();
+        assert_eq!(spans(&intervals), [
+            (at(31, 8), at(31, 12), IntervalStatus::Complete),
+            (at(31, 13), at(31, 17), IntervalStatus::Complete),
+        ]);
+        assert_eq!(seconds_worked(&intervals), Some(8 * 3600));
+    }
+
+    #[test]
+    fn pulls_in_shifts_crossing_the_range() {
+        let storage = storage(&[(at(29, 8), IO::I), (at(29, 9), IO::O), (at(30, 22), IO::I), (at(31, 6), IO::O)]);
+
+        let intervals = get_intervals(&storage, day(31), day(31)).unwrap();
+        assert_eq!(spans(&intervals), [(at(30, 22), at(31, 6), IntervalStatus::Complete)]);
+        assert_eq!(seconds_worked(&intervals_for_day(&intervals, day(31), OvernightRule::Split)), Some(6 * 3600));
+        assert_eq!(seconds_worked(&intervals_for_day(&intervals, day(31), OvernightRule::Start)), Some(0));
+        assert_eq!(seconds_worked(&intervals_for_day(&intervals, day(31), OvernightRule::End)), Some(8 * 3600));
+
+        let intervals = get_intervals(&storage, day(30), day(30)).unwrap();
+        assert_eq!(spans(&intervals), [(at(30, 22), at(31, 6), IntervalStatus::Complete)]);
+    }
+
+    #[test]
+    fn first_and_last_days() {
+        let first = NaiveDate::MIN.and_hms_opt(8, 0, 0).unwrap();
+        let last = NaiveDate::MAX.and_hms_opt(22, 0, 0).unwrap();
+        let storage = storage(&[(first, IO::I), (last, IO::I)]);
+        assert_eq!(get_intervals(&storage, NaiveDate::MIN, NaiveDate::MIN).unwrap().len(), 1);
+        let intervals = get_intervals(&storage, NaiveDate::MAX, NaiveDate::MAX).unwrap();
+        assert_eq!(intervals_for_day(&intervals, NaiveDate::MAX, OvernightRule::Split).len(), 1);
+    }
+
+    #[test]
+    fn keeps_incomplete_intervals_to_one_day() {
+        let storage = storage(&[(at(30, 8), IO::I), (at(31, 9), IO::I), (at(31, 17), IO::O), (at(31, 18), IO::O)]);
+        let intervals = get_intervals(&storage, day(30), day(31)).unwrap();
+        assert_eq!(spans(&intervals), [
+            (at(30, 8), end_of_day(day(30)), IntervalStatus::MissingEnd),
+            (at(31, 9), at(31, 17), IntervalStatus::Complete),
+            (at(31, 17), at(31, 18), IntervalStatus::MissingStart),
+        ]);
+        assert_eq!(seconds_worked(&intervals_for_day(&intervals, day(31), OvernightRule::Split)), None);
+    }
+}
@@ -0,0 +1,44 @@
+use chrono::{Datelike, NaiveDate};
+use crate::OvernightRule;
+use recolored::Colorize;
+
+use super::fancy_report::format_total;
+use super::interval::{Storage, get_intervals, intervals_for_day, seconds_worked};
+
+pub fn range_report(storage: &dyn Storage, from: NaiveDate, to: NaiveDate, rule: OvernightRule) -> Result<(), anyhow::Error> {
+    let all_intervals = get_intervals(storage, from, to)?;
+    let mut total = 0_i64;
+    let mut incomplete_days: Vec<NaiveDate> = vec![];
+
+    println!("      TIMESHEET {from} - {to}");
+    println!("┌────────────┬───────┬────────────┐");
+    println!("│    DATE    │  DAY  │   WORKED   │");
+    println!("├────────────┼───────┼────────────┤");
+    for date in from.iter_days().take_while(|date| date <= &to) {
+        let intervals = intervals_for_day(&all_intervals, date, rule);
+        match seconds_worked(&intervals) {
+            Some(seconds) => {
+                total += seconds;
+                println!("│ {date} │  {}  │ {:>10} │", date.weekday(), format_total(seconds));
+            },
+            None => {
+                incomplete_days.push(date);
+                println!("│ {date} │  {}  │ {} │", date.weekday(), "INCOMPLETE".red());
+            },
+        }
+    }
+    println!("└────────────┴───────┴────────────┘");
+
+    let notice = "TOTAL TIME WORKED: ".blue();
+    println!("{}{}", notice, format_total(total).blue().bold());
+
+    if !incomplete_days.is_empty() {
+        print!("{}", "ERROR".red().bold());
+        println!(" {}", "some days have incomplete intervals and are not counted in the total".red());
+        for date in incomplete_days.iter() {
+            println!("\tRun `timest report -d {date}` to see the incomplete intervals");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use chrono::NaiveDateTime;
+use crate::{Config, IO, StatusArgs, Timesheet, check_time::ClockCheck, sequence::prev_entry};
+
+use super::fancy_report::format_total;
+use super::interval::*;
+
+/// Whether you are clocked in right now, and how much you have worked today.
+struct Status {
+    /// The latest entry at or before now
+    last: Option<Record>,
+    /// Seconds worked today in complete intervals, plus the open one
+    today_seconds: i64,
+    /// Whether today has intervals other than the open one that are missing an end or start
+    incomplete: bool,
+}
+
+pub fn status_cmd(timesheet: &Timesheet, args: StatusArgs) -> Result<(), anyhow::Error> {
+    let now = chrono::Local::now().naive_local();
+    let status = get_status(timesheet.storage(), timesheet.config(), now)?;
+
+    if args.terse {
+        print_terse(&status, now);
+    } else {
+        print_status(&status, now);
+        // Only the last check; status never waits on a time server
+        match ClockCheck::load(timesheet.connection())? {
+            Some(check) => println!("System clock: {} (checked {})", check.describe_offset(), check.checked_at),
+            None => println!("System clock: not checked yet"),
+        }
+    }
+    Ok(())
+}
+
+fn get_status(storage: &dyn Storage, config: &Config, now: NaiveDateTime) -> Result<Status, anyhow::Error> {
+    let today = now.date();
+    let last = prev_entry(storage, now, None)?;
+    let clocked_in_since = last.as_ref()
+        .filter(|last| last.io == IO::I)
+        .map(|last| last.timestamp);
+
+    let from = clocked_in_since.map_or(today, |since| since.date().min(today));
+    let mut intervals = get_intervals(storage, from, today)?;
+
+    // The open interval runs until now, not until the end of the day
+    if let Some(since) = clocked_in_since {
+        let open = intervals.iter_mut()
+            .find(|interval| interval.start == since && interval.status != IntervalStatus::MissingStart);
+        if let Some(open) = open {
+            open.end = now;
+            open.status = IntervalStatus::Complete;
+        }
+    }
+
+    let intervals = intervals_for_day(&intervals, today, config.overnight);
+    let today_seconds = intervals.iter()
+        .filter(|interval| interval.status == IntervalStatus::Complete)
+        .map(|interval| interval.duration())
+        .sum();
+    let incomplete = seconds_worked(&intervals).is_none();
+
+    Ok(Status { last, today_seconds, incomplete })
+}
+
+fn print_status(status: &Status, now: NaiveDateTime) {
+    match &status.last {
+        None => println!("Not clocked in yet"),
+        Some(last) => {
+            let elapsed = format_total((now - last.timestamp).num_seconds());
+            let since = if last.timestamp.date() == now.date() {
+                last.timestamp.time().to_string()
+            } else {
+                last.timestamp.to_string()
+            };
+            println!("Clocked {} since {since} ({elapsed})", last.io.as_word());
+        },
+    }
+    print!("Today: {}", format_total(status.today_seconds));
+    if status.incomplete {
+        print!(" (not counting incomplete intervals, see `timest report`)");
+    }
+    println!();
+}
+
+/// One tab separated line: in/out/none, the time of the last entry, seconds
+/// since then, seconds worked today, and complete/incomplete.
+fn print_terse(status: &Status, now: NaiveDateTime) {
+    let (state, since, elapsed) = match &status.last {
+        None => ("none", String::new(), String::new()),
+        Some(last) => (
+            last.io.as_word(),
+            last.timestamp.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            (now - last.timestamp).num_seconds().to_string(),
+        ),
+    };
+    let complete = if status.incomplete { "incomplete" } else { "complete" };
+    println!("{state}\t{since}\t{elapsed}\t{}\t{complete}", status.today_seconds);
+}
@@ -0,0 +1,103 @@
+use crate::{Error, IO, check_time::prompt_err, storage::{Record, Storage}};
+use chrono::{Duration, NaiveDateTime, SubsecRound};
+
+/// Longest plausible shift. Entries further apart than this on different
+/// days are not treated as part of the same in/out sequence.
+pub const MAX_SHIFT_HOURS: i64 = 16;
+
+fn related(a: NaiveDateTime, b: NaiveDateTime) -> bool {
+    a.date() == b.date() || (a - b).abs() <= Duration::hours(MAX_SHIFT_HOURS)
+}
+
+/// The last entry at or before `datetime`, ignoring the entry `exclude`.
+pub fn prev_entry(storage: &dyn Storage, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+    storage.last_until(datetime, exclude)
+}
+
+/// The first entry after `datetime`, ignoring the entry `exclude`.
+pub fn next_entry(storage: &dyn Storage, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+    storage.first_after(datetime, exclude)
+}
+
+/// The entries on either side of a clock `io` at `datetime`, ignoring the
+/// entry `exclude`. A clock out goes before any clock ins at the same time,
+/// so that it can end an interval right where the next one starts.
+fn neighbors(storage: &dyn Storage, datetime: NaiveDateTime, io: IO, exclude: Option<i64>) -> Result<(Option<Record>, Option<Record>), anyhow::Error> {
+    let prev = prev_entry(storage, datetime, exclude)?;
+    let next = next_entry(storage, datetime, exclude)?;
+    let second = datetime.trunc_subsecs(0);
+    let same_time = |record: &Record| record.timestamp.trunc_subsecs(0) == second;
+    if io == IO::I || !prev.as_ref().is_some_and(|prev| prev.io == IO::I && same_time(prev)) {
+        return Ok((prev, next));
+    }
+
+    let same_time: Vec<Record> = storage.range(datetime.date(), datetime.date())?
+        .into_iter()
+        .filter(|record| same_time(record) && Some(record.id) != exclude)
+        .collect();
+    let first_in = same_time.iter().position(|record| record.io == IO::I).expect("There is a clock in at this time");
+    let prev = match first_in {
+        0 => match second.checked_sub_signed(Duration::nanoseconds(1)) {
+            Some(before) => prev_entry(storage, before, exclude)?,
+            None => None,
+        },
+        index => Some(same_time[index - 1].clone()),
+    };
+    Ok((prev, Some(same_time[first_in].clone())))
+}
+
+/// What is wrong with `after` coming straight after `before`, if anything.
+/// `before` is ignored if it is too far away to belong to the same shift.
+fn pair_problem(before: Option<(NaiveDateTime, IO)>, after: (NaiveDateTime, IO)) -> Option<String> {
+    let before = before.filter(|(timestamp, _)| related(*timestamp, after.0));
+    match (before, after) {
+        (Some((before_time, before_io)), (after_time, after_io)) if before_io == after_io => Some(format!(
+            "clock {} at {after_time} would follow another clock {} at {before_time}",
+            after_io.as_word(), before_io.as_word()
+        )),
+        (None, (after_time, IO::O)) => Some(format!(
+            "clock out at {after_time} would have no clock in before it"
+        )),
+        _ => None,
+    }
+}
+
+/// Ways that a clock `io` at `datetime` would break the in/out sequence.
+/// `moved` is the id and current timestamp of the entry being fixed, if any.
+pub fn sequence_problems(storage: &dyn Storage, datetime: NaiveDateTime, io: IO, moved: Option<(i64, NaiveDateTime)>) -> Result<Vec<String>, anyhow::Error> {
+    let exclude = moved.map(|(id, _)| id);
+    let as_pair = |neighbor: &Option<Record>| neighbor.as_ref().map(|neighbor| (neighbor.timestamp, neighbor.io));
+    let mut problems = vec![];
+
+    let (prev, next) = neighbors(storage, datetime, io, exclude)?;
+    problems.extend(pair_problem(as_pair(&prev), (datetime, io)));
+    if let Some(next) = as_pair(&next).filter(|(timestamp, _)| related(*timestamp, datetime)) {
+        problems.extend(pair_problem(Some((datetime, io)), next));
+    }
+
+    // Moving an entry away leaves the entries on either side of it back to back
+    if let Some((id, old_datetime)) = moved {
+        let old_io = storage.get(id)?.map_or(io, |record| record.io);
+        let (old_prev, old_next) = neighbors(storage, old_datetime, old_io, Some(id))?;
+        let same_place = prev.as_ref().map(|prev| prev.id) == old_prev.as_ref().map(|prev| prev.id)
+            && next.as_ref().map(|next| next.id) == old_next.as_ref().map(|next| next.id);
+        if let Some(old_next) = as_pair(&old_next).filter(|(timestamp, _)| !same_place && related(*timestamp, old_datetime)) {
+            if let Some(problem) = pair_problem(as_pair(&old_prev), old_next) {
+                problems.push(format!("moving entry {id} away from {old_datetime}: {problem}"));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks the in/out sequence before an entry is written. Problems are
+/// confirmed with the user unless `force` is set, and fail with `no_input`.
+pub fn validate_sequence(storage: &dyn Storage, datetime: NaiveDateTime, io: IO, moved: Option<(i64, NaiveDateTime)>, force: bool, no_input: bool) -> Result<(), anyhow::Error> {
+    let problems = sequence_problems(storage, datetime, io, moved)?;
+    if problems.is_empty() || force {
+        return Ok(());
+    }
+    let message = problems.join("; ");
+    Ok(prompt_err(&message, Error::InvalidSequence(message.clone()), no_input)?)
+}
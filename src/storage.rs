@@ -0,0 +1,490 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use crate::IO;
+
+mod sqlite_backend;
+
+mod memory;
+pub use memory::MemoryStorage;
+
+/// A clock in or out as stored in the `times` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Record {
+    pub id: i64,
+    pub timestamp: NaiveDateTime,
+    pub io: IO,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    /// What was worked on
+    pub note: Option<String>,
+    /// Seconds added to the system time when clocking, if it was corrected
+    /// with the offset measured by a time server
+    pub correction: Option<i64>,
+}
+
+/// A clock in or out that has not been saved yet.
+#[derive(Debug, Clone)]
+pub struct NewRecord {
+    pub timestamp: NaiveDateTime,
+    pub io: IO,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    pub note: Option<String>,
+    pub correction: Option<i64>,
+}
+
+/// Changes to a saved record. Fields left as `None` are kept as they are,
+/// and an empty project, tag or note removes it.
+#[derive(Debug, Clone, Default)]
+pub struct RecordUpdate {
+    pub timestamp: Option<NaiveDateTime>,
+    pub io: Option<IO>,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    pub note: Option<String>,
+}
+
+impl RecordUpdate {
+    /// `record` as it will be after this update.
+    pub fn apply(&self, record: &Record) -> Record {
+        Record {
+            id: record.id,
+            timestamp: self.timestamp.unwrap_or(record.timestamp),
+            io: self.io.unwrap_or(record.io),
+            project: updated_text(&self.project, &record.project),
+            tag: updated_text(&self.tag, &record.tag),
+            note: updated_text(&self.note, &record.note),
+            // A timestamp given by hand replaces the corrected one
+            correction: record.correction.filter(|_| self.timestamp.is_none_or(|timestamp| timestamp == record.timestamp)),
+        }
+    }
+}
+
+/// `new` in place of `old`, or `old` if there is no `new`. An empty `new`
+/// clears it.
+fn updated_text(new: &Option<String>, old: &Option<String>) -> Option<String> {
+    match new {
+        Some(new) if new.is_empty() => None,
+        Some(new) => Some(new.clone()),
+        None => old.clone(),
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}  {}  {:<3}", self.id, self.timestamp.format("%Y-%m-%d %H:%M:%S"), self.io.as_word())?;
+        if let Some(project) = &self.project {
+            write!(f, "  project: {project}")?;
+        }
+        if let Some(tag) = &self.tag {
+            write!(f, "  tag: {tag}")?;
+        }
+        if let Some(note) = &self.note {
+            write!(f, "  note: {note}")?;
+        }
+        if let Some(correction) = self.correction {
+            write!(f, "  corrected: {correction:+}s")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Record> for NewRecord {
+    fn from(record: Record) -> Self {
+        Self {
+            timestamp: record.timestamp,
+            io: record.io,
+            project: record.project,
+            tag: record.tag,
+            note: record.note,
+            correction: record.correction,
+        }
+    }
+}
+
+/// A deleted record, kept until the trash is purged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trashed {
+    pub record: Record,
+    pub deleted_at: NaiveDateTime,
+}
+
+/// What a change did to an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+    /// Removed from the trash for good. Can't be undone
+    Purge,
+}
+
+/// Why a change was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    /// Clocking, fixing, deleting and so on
+    Edit,
+    /// Undoing the change in `reverts`
+    Undo,
+    /// Redoing the change in `reverts`
+    Redo,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Insert => "insert",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Purge => "purge",
+        }
+    }
+}
+
+impl ChangeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSource::Edit => "edit",
+            ChangeSource::Undo => "undo",
+            ChangeSource::Redo => "redo",
+        }
+    }
+}
+
+impl TryFrom<&str> for ChangeKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "insert" => Ok(ChangeKind::Insert),
+            "update" => Ok(ChangeKind::Update),
+            "delete" => Ok(ChangeKind::Delete),
+            "purge" => Ok(ChangeKind::Purge),
+            _ => Err(anyhow::anyhow!("unknown change `{value}`")),
+        }
+    }
+}
+
+impl TryFrom<&str> for ChangeSource {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "edit" => Ok(ChangeSource::Edit),
+            "undo" => Ok(ChangeSource::Undo),
+            "redo" => Ok(ChangeSource::Redo),
+            _ => Err(anyhow::anyhow!("unknown change source `{value}`")),
+        }
+    }
+}
+
+/// An entry in the change log, with the entry as it was before and after.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub id: i64,
+    pub changed_at: NaiveDateTime,
+    pub entry_id: i64,
+    pub kind: ChangeKind,
+    pub source: ChangeSource,
+    /// The change this one undoes or redoes
+    pub reverts: Option<i64>,
+    pub old: Option<Record>,
+    pub new: Option<Record>,
+}
+
+/// A change to make: entry `entry` becomes `new`, or is moved to the trash if
+/// `new` is `None`. A new entry is added if `entry` is `None`, and an entry in
+/// the trash is restored.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub entry: Option<i64>,
+    pub new: Option<NewRecord>,
+    pub source: ChangeSource,
+    pub reverts: Option<i64>,
+}
+
+impl Edit {
+    fn new(entry: Option<i64>, new: Option<NewRecord>) -> Self {
+        Self { entry, new, source: ChangeSource::Edit, reverts: None }
+    }
+}
+
+/// Which edits can be undone, and which undos can be redone, from the log.
+/// The next change to undo or redo is last. Neither goes back past a change
+/// to an entry that has since been purged.
+pub fn undo_stacks(changes: &[Change]) -> (Vec<&Change>, Vec<&Change>) {
+    let mut done: Vec<&Change> = vec![];
+    let mut undone: Vec<&Change> = vec![];
+    for change in changes {
+        if change.kind == ChangeKind::Purge {
+            for stack in [&mut done, &mut undone] {
+                if let Some(index) = stack.iter().rposition(|earlier| earlier.entry_id == change.entry_id) {
+                    stack.drain(..=index);
+                }
+            }
+            continue;
+        }
+        match (change.source, change.reverts) {
+            (ChangeSource::Undo, Some(reverted)) => {
+                if let Some(index) = done.iter().rposition(|change| change.id == reverted) {
+                    undone.push(done.remove(index));
+                }
+            },
+            (ChangeSource::Redo, Some(reverted)) => {
+                if let Some(index) = undone.iter().rposition(|change| change.id == reverted) {
+                    done.push(undone.remove(index));
+                }
+            },
+            _ => {
+                done.push(change);
+                // A new edit can't be mixed with changes that were undone before it
+                undone.clear();
+            },
+        }
+    }
+    (done, undone)
+}
+
+/// Where clock ins and outs are kept. Records are ordered by timestamp, then
+/// clock outs before clock ins, then by id. Timestamps are stored to the second. Every change is kept in an
+/// append-only log so that it can be undone. Deleted records are kept in the
+/// trash, where reads don't see them, until they are purged.
+///
+/// SQLite is the real backend, implemented on `sqlite::Connection`.
+/// [`MemoryStorage`] keeps records in memory, for tests.
+pub trait Storage {
+    fn get(&self, id: i64) -> Result<Option<Record>, anyhow::Error>;
+
+    /// Records on the days `from` through `to`.
+    fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>, anyhow::Error>;
+
+    /// The last record at or before `datetime`, other than `exclude`.
+    fn last_until(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error>;
+
+    /// The first record after `datetime`, other than `exclude`.
+    fn first_after(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error>;
+
+    /// Makes `edits` in order and adds them to the change log, all or
+    /// nothing. Returns the ids of the entries.
+    fn write_all(&self, edits: Vec<Edit>) -> Result<Vec<i64>, anyhow::Error>;
+
+    /// The change log, oldest first.
+    fn changes(&self) -> Result<Vec<Change>, anyhow::Error>;
+
+    /// Deleted records, most recently deleted first.
+    fn trash(&self) -> Result<Vec<Trashed>, anyhow::Error>;
+
+    /// Removes records deleted before `before` for good, logging each one.
+    /// Returns how many.
+    fn purge(&self, before: NaiveDateTime) -> Result<usize, anyhow::Error>;
+
+    /// Makes `edit` and adds it to the change log, both or neither. Returns
+    /// the id of the entry.
+    fn write(&self, edit: Edit) -> Result<i64, anyhow::Error> {
+        Ok(self.write_all(vec![edit])?[0])
+    }
+
+    /// Saves `record` and returns its id.
+    fn insert(&self, record: NewRecord) -> Result<i64, anyhow::Error> {
+        self.write(Edit::new(None, Some(record)))
+    }
+
+    /// Saves all of `records` or none of them, and returns their ids.
+    fn insert_all(&self, records: Vec<NewRecord>) -> Result<Vec<i64>, anyhow::Error> {
+        self.write_all(records.into_iter().map(|record| Edit::new(None, Some(record))).collect())
+    }
+
+    /// Changes the record `id`. Returns false if there is no such record.
+    fn update(&self, id: i64, update: RecordUpdate) -> Result<bool, anyhow::Error> {
+        let Some(old) = self.get(id)? else {
+            return Ok(false);
+        };
+        self.write(Edit::new(Some(id), Some(update.apply(&old).into())))?;
+        Ok(true)
+    }
+
+    /// Deletes the record `id`. Returns false if there is no such record.
+    fn delete(&self, id: i64) -> Result<bool, anyhow::Error> {
+        if self.get(id)?.is_none() {
+            return Ok(false);
+        }
+        self.write(Edit::new(Some(id), None))?;
+        Ok(true)
+    }
+
+    /// Takes the record `id` back out of the trash. Returns false if it is
+    /// not in the trash.
+    fn restore(&self, id: i64) -> Result<bool, anyhow::Error> {
+        let Some(trashed) = self.trash()?.into_iter().find(|trashed| trashed.record.id == id) else {
+            return Ok(false);
+        };
+        self.write(Edit::new(Some(id), Some(trashed.record.into())))?;
+        Ok(true)
+    }
+
+    /// Reverts the latest change that has not been undone yet, and returns it.
+    fn undo(&self) -> Result<Option<Change>, anyhow::Error> {
+        let changes = self.changes()?;
+        let Some(&change) = undo_stacks(&changes).0.last() else {
+            return Ok(None);
+        };
+        self.write(Edit {
+            entry: Some(change.entry_id),
+            new: change.old.clone().map(NewRecord::from),
+            source: ChangeSource::Undo,
+            reverts: Some(change.id),
+        })?;
+        Ok(Some(change.clone()))
+    }
+
+    /// Makes the latest undone change again, and returns it.
+    fn redo(&self) -> Result<Option<Change>, anyhow::Error> {
+        let changes = self.changes()?;
+        let Some(&change) = undo_stacks(&changes).1.last() else {
+            return Ok(None);
+        };
+        self.write(Edit {
+            entry: Some(change.entry_id),
+            new: change.new.clone().map(NewRecord::from),
+            source: ChangeSource::Redo,
+            reverts: Some(change.id),
+        })?;
+        Ok(Some(change.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 5, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    fn new_record(timestamp: NaiveDateTime, io: IO) -> NewRecord {
+        NewRecord { timestamp, io, project: None, tag: None, note: None, correction: None }
+    }
+
+    /// Both backends should behave the same
+    fn exercise(storage: &dyn Storage) {
+        let first = storage.insert(new_record(at(30, 22), IO::I)).unwrap();
+        let second = storage.insert(new_record(at(31, 6), IO::O)).unwrap();
+        let third = storage.insert(new_record(at(31, 8), IO::I)).unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2023, 5, 31).unwrap();
+        let ids = |records: Vec<Record>| records.iter().map(|record| record.id).collect::<Vec<_>>();
+        assert_eq!(ids(storage.range(day, day).unwrap()), [second, third]);
+        assert_eq!(storage.last_until(at(31, 6), None).unwrap().unwrap().id, second);
+        assert_eq!(storage.last_until(at(31, 6), Some(second)).unwrap().unwrap().id, first);
+        assert_eq!(storage.first_after(at(31, 6), None).unwrap().unwrap().id, third);
+        assert!(storage.first_after(at(31, 8), None).unwrap().is_none());
+
+        let update = RecordUpdate {
+            io: Some(IO::O),
+            project: Some("acme".to_owned()),
+            note: Some("standup".to_owned()),
+            ..Default::default()
+        };
+        assert!(storage.update(third, update).unwrap());
+        let updated = storage.get(third).unwrap().unwrap();
+        assert_eq!((updated.timestamp, updated.io, updated.project.as_deref()), (at(31, 8), IO::O, Some("acme")));
+
+        assert!(storage.delete(first).unwrap());
+        assert!(!storage.delete(first).unwrap());
+        assert!(storage.get(first).unwrap().is_none());
+        assert!(!storage.update(first, RecordUpdate::default()).unwrap());
+
+        // Every change is logged, and can be undone and redone in order
+        let kinds = |storage: &dyn Storage| storage.changes().unwrap().iter()
+            .map(|change| (change.kind, change.source))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds(storage), [
+            (ChangeKind::Insert, ChangeSource::Edit),
+            (ChangeKind::Insert, ChangeSource::Edit),
+            (ChangeKind::Insert, ChangeSource::Edit),
+            (ChangeKind::Update, ChangeSource::Edit),
+            (ChangeKind::Delete, ChangeSource::Edit),
+        ]);
+
+        assert_eq!(storage.undo().unwrap().unwrap().kind, ChangeKind::Delete);
+        assert_eq!(storage.get(first).unwrap().unwrap().timestamp, at(30, 22));
+        assert_eq!(storage.undo().unwrap().unwrap().kind, ChangeKind::Update);
+        assert_eq!(storage.get(third).unwrap().unwrap(), Record { id: third, timestamp: at(31, 8), io: IO::I, project: None, tag: None, note: None, correction: None });
+        assert_eq!(storage.redo().unwrap().unwrap().kind, ChangeKind::Update);
+        let redone = storage.get(third).unwrap().unwrap();
+        assert_eq!((redone.project.as_deref(), redone.note.as_deref()), (Some("acme"), Some("standup")));
+
+        // Undoing every insert leaves nothing, and a new change stops redo
+        for _ in 0..4 {
+            storage.undo().unwrap().unwrap();
+        }
+        assert!(storage.undo().unwrap().is_none());
+        assert!(storage.range(day, day).unwrap().is_empty());
+        storage.insert(new_record(at(31, 9), IO::I)).unwrap();
+        assert!(storage.redo().unwrap().is_none());
+        assert_eq!(storage.changes().unwrap().len(), 13);
+
+        // Deleted records wait in the trash until they are restored or purged
+        let fourth = storage.insert(new_record(at(31, 17), IO::O)).unwrap();
+        assert!(fourth > third);
+        storage.delete(fourth).unwrap();
+        assert!(storage.first_after(at(31, 9), None).unwrap().is_none());
+        let trash = storage.trash().unwrap();
+        assert_eq!(trash.len(), 4);
+        assert_eq!(trash[0].record.id, fourth);
+        assert!(storage.restore(fourth).unwrap());
+        assert!(!storage.restore(fourth).unwrap());
+        assert_eq!(storage.first_after(at(31, 9), None).unwrap().unwrap().id, fourth);
+        let oldest = trash.last().unwrap().deleted_at;
+        assert_eq!(storage.purge(oldest).unwrap(), 0);
+        assert_eq!(storage.purge(trash[0].deleted_at + chrono::Duration::seconds(1)).unwrap(), 3);
+        assert!(storage.trash().unwrap().is_empty());
+
+        // Several edits are saved all together or not at all
+        let changes = storage.changes().unwrap().len();
+        let bad = Edit::new(None, None);
+        assert!(storage.write_all(vec![Edit::new(None, Some(new_record(at(31, 18), IO::I))), bad]).is_err());
+        assert_eq!(storage.changes().unwrap().len(), changes);
+        assert_eq!(storage.range(day, day).unwrap().len(), 2);
+        let pair = storage.insert_all(vec![new_record(at(31, 18), IO::I), new_record(at(31, 19), IO::O)]).unwrap();
+        assert_eq!(ids(storage.range(day, day).unwrap())[2..], pair);
+
+        // Purged ids are never given out again, so undo can't mix two entries up
+        let purged = storage.insert(new_record(at(31, 20), IO::I)).unwrap();
+        storage.delete(purged).unwrap();
+        assert_eq!(storage.purge(storage.trash().unwrap()[0].deleted_at + chrono::Duration::seconds(1)).unwrap(), 1);
+        assert_eq!(storage.changes().unwrap().last().unwrap().kind, ChangeKind::Purge);
+        assert!(storage.undo().unwrap().is_none());
+        let next = storage.insert(new_record(at(31, 21), IO::O)).unwrap();
+        assert!(next > purged);
+        assert_eq!(storage.undo().unwrap().unwrap().entry_id, next);
+        assert!(storage.undo().unwrap().is_none());
+        assert!(storage.get(purged).unwrap().is_none());
+        assert_eq!(storage.trash().unwrap()[0].record.id, next);
+    }
+
+    #[test]
+    fn empty_labels_clear() {
+        let record = Record { id: 1, timestamp: at(31, 8), io: IO::I, project: Some("acme".to_owned()), tag: Some("meeting".to_owned()), note: Some("standup".to_owned()), correction: None };
+        let updated = RecordUpdate { project: Some(String::new()), ..Default::default() }.apply(&record);
+        assert_eq!((updated.project, updated.tag.as_deref()), (None, Some("meeting")));
+        let updated = RecordUpdate { tag: Some(String::new()), ..Default::default() }.apply(&record);
+        assert_eq!((updated.project.as_deref(), updated.tag), (Some("acme"), None));
+        let updated = RecordUpdate { note: Some(String::new()), ..Default::default() }.apply(&record);
+        assert_eq!((updated.project.as_deref(), updated.note), (Some("acme"), None));
+    }
+
+    #[test]
+    fn memory_backend() {
+        exercise(&MemoryStorage::default());
+    }
+
+    #[test]
+    fn sqlite_backend() {
+        let conn = sqlite::open(":memory:").unwrap();
+        crate::migrations::migrate(&conn, std::path::Path::new(":memory:")).unwrap();
+        exercise(&conn);
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use std::cell::{Cell, RefCell};
+
+use crate::IO;
+use super::{Change, ChangeKind, ChangeSource, Edit, Record, Storage, Trashed};
+
+/// Records kept in memory and lost when dropped. Useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    records: RefCell<Vec<Record>>,
+    trash: RefCell<Vec<Trashed>>,
+    changes: RefCell<Vec<Change>>,
+    /// The highest id ever used. Ids aren't reused, even after a purge
+    last_id: Cell<i64>,
+}
+
+impl MemoryStorage {
+    /// Records that match `keep`, in storage order.
+    fn sorted(&self, keep: impl Fn(&Record) -> bool) -> Vec<Record> {
+        let mut records: Vec<Record> = self.records.borrow().iter()
+            .filter(|record| keep(record))
+            .cloned()
+            .collect();
+        records.sort_by_key(|record| (record.timestamp, record.io == IO::I, record.id));
+        records
+    }
+
+    /// Makes `edit` and logs it.
+    fn write_one(&self, edit: Edit) -> Result<i64, anyhow::Error> {
+        let old = edit.entry.map(|id| self.get(id)).transpose()?.flatten();
+        let mut records = self.records.borrow_mut();
+        let mut trash = self.trash.borrow_mut();
+
+        let entry_id = match (edit.entry, &edit.new) {
+            (Some(id), _) => id,
+            (None, Some(_)) => self.last_id.get() + 1,
+            (None, None) => return Err(anyhow::anyhow!("an edit needs an entry or a new record")),
+        };
+        self.last_id.set(self.last_id.get().max(entry_id));
+        // Stored to the second, like in SQLite
+        let now = chrono::Local::now().naive_local().with_nanosecond(0).expect("0 is a valid nanosecond");
+        if edit.new.is_some() {
+            trash.retain(|trashed| trashed.record.id != entry_id);
+        }
+        if let Some(index) = records.iter().position(|record| record.id == entry_id) {
+            let record = records.remove(index);
+            if edit.new.is_none() {
+                trash.push(Trashed { record, deleted_at: now });
+            }
+        }
+        let new = edit.new.map(|new| Record {
+            id: entry_id,
+            timestamp: new.timestamp,
+            io: new.io,
+            project: new.project,
+            tag: new.tag,
+            note: new.note,
+            correction: new.correction,
+        });
+        records.extend(new.clone());
+
+        let mut changes = self.changes.borrow_mut();
+        let id = changes.len() as i64 + 1;
+        changes.push(Change {
+            id,
+            changed_at: now,
+            entry_id,
+            kind: match (&old, &new) {
+                (None, _) => ChangeKind::Insert,
+                (_, None) => ChangeKind::Delete,
+                _ => ChangeKind::Update,
+            },
+            source: edit.source,
+            reverts: edit.reverts,
+            old,
+            new,
+        });
+        Ok(entry_id)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn write_all(&self, edits: Vec<Edit>) -> Result<Vec<i64>, anyhow::Error> {
+        let records = self.records.borrow().clone();
+        let trash = self.trash.borrow().clone();
+        let changes = self.changes.borrow().clone();
+        let last_id = self.last_id.get();
+        let ids: Result<Vec<i64>, _> = edits.into_iter().map(|edit| self.write_one(edit)).collect();
+        // Put everything back if any of them failed
+        if ids.is_err() {
+            *self.records.borrow_mut() = records;
+            *self.trash.borrow_mut() = trash;
+            *self.changes.borrow_mut() = changes;
+            self.last_id.set(last_id);
+        }
+        ids
+    }
+
+    fn trash(&self) -> Result<Vec<Trashed>, anyhow::Error> {
+        let mut trash = self.trash.borrow().clone();
+        trash.sort_by_key(|trashed| std::cmp::Reverse((trashed.deleted_at, trashed.record.id)));
+        Ok(trash)
+    }
+
+    fn purge(&self, before: NaiveDateTime) -> Result<usize, anyhow::Error> {
+        let (purged, kept) = self.trash.take().into_iter()
+            .partition::<Vec<_>, _>(|trashed| trashed.deleted_at < before);
+        *self.trash.borrow_mut() = kept;
+        let now = chrono::Local::now().naive_local().with_nanosecond(0).expect("0 is a valid nanosecond");
+        let mut changes = self.changes.borrow_mut();
+        for trashed in purged.iter() {
+            let id = changes.len() as i64 + 1;
+            changes.push(Change {
+                id,
+                changed_at: now,
+                entry_id: trashed.record.id,
+                kind: ChangeKind::Purge,
+                source: ChangeSource::Edit,
+                reverts: None,
+                old: Some(trashed.record.clone()),
+                new: None,
+            });
+        }
+        Ok(purged.len())
+    }
+
+    fn changes(&self) -> Result<Vec<Change>, anyhow::Error> {
+        Ok(self.changes.borrow().clone())
+    }
+
+    fn get(&self, id: i64) -> Result<Option<Record>, anyhow::Error> {
+        Ok(self.records.borrow().iter().find(|record| record.id == id).cloned())
+    }
+
+    fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>, anyhow::Error> {
+        Ok(self.sorted(|record| (from..=to).contains(&record.timestamp.date())))
+    }
+
+    fn last_until(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+        Ok(self.sorted(|record| record.timestamp <= datetime && Some(record.id) != exclude).pop())
+    }
+
+    fn first_after(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+        Ok(self.sorted(|record| record.timestamp > datetime && Some(record.id) != exclude).into_iter().next())
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlite::State;
+use crate::IO;
+
+use super::{Change, ChangeKind, ChangeSource, Edit, Record, Storage, Trashed};
+
+/// Adds an entry, or replaces entry `:id` if it exists. A NULL `:id` picks a new one.
+const PUT_SQL: &str = "
+    INSERT INTO times (
+        id, timestamp, io, project, tag, note, correction
+    ) VALUES (
+        :id, :timestamp, :io, :project, :tag, :note, :correction
+    )
+    ON CONFLICT(id) DO UPDATE SET
+        timestamp = excluded.timestamp, io = excluded.io,
+        project = excluded.project, tag = excluded.tag,
+        note = excluded.note, correction = excluded.correction,
+        deleted_at = NULL
+";
+
+/// Deleting only moves entries to the trash
+const DELETE_SQL: &str = "
+    UPDATE times SET deleted_at = :deleted_at
+    WHERE id = :id AND deleted_at IS NULL
+";
+
+const TRASH_SQL: &str = "
+    SELECT * FROM times
+    WHERE deleted_at IS NOT NULL
+    ORDER BY deleted_at DESC, id DESC
+";
+
+const PURGEABLE_SQL: &str = "
+    SELECT * FROM times
+    WHERE deleted_at IS NOT NULL AND deleted_at < ?
+    ORDER BY id
+";
+
+const PURGE_SQL: &str = "
+    DELETE FROM times WHERE id = ?
+";
+
+const LOG_SQL: &str = "
+    INSERT INTO changes (
+        changed_at, entry_id, kind, source, reverts,
+        old_timestamp, old_io, old_project, old_tag, old_note, old_correction,
+        new_timestamp, new_io, new_project, new_tag, new_note, new_correction
+    ) VALUES (
+        :changed_at, :entry_id, :kind, :source, :reverts,
+        :old_timestamp, :old_io, :old_project, :old_tag, :old_note, :old_correction,
+        :new_timestamp, :new_io, :new_project, :new_tag, :new_note, :new_correction
+    )
+";
+
+const CHANGES_SQL: &str = "
+    SELECT * FROM changes ORDER BY id
+";
+
+const GET_SQL: &str = "
+    SELECT * FROM times WHERE id = ? AND deleted_at IS NULL
+";
+
+const RANGE_SQL: &str = "
+    SELECT * FROM times
+    WHERE Date(timestamp) BETWEEN :from AND :to AND deleted_at IS NULL
+    ORDER BY timestamp, io DESC, id
+";
+
+const LAST_UNTIL_SQL: &str = "
+    SELECT * FROM times
+    WHERE timestamp <= :datetime AND id != :exclude AND deleted_at IS NULL
+    ORDER BY timestamp DESC, io, id DESC
+    LIMIT 1
+";
+
+const FIRST_AFTER_SQL: &str = "
+    SELECT * FROM times
+    WHERE timestamp > :datetime AND id != :exclude AND deleted_at IS NULL
+    ORDER BY timestamp, io DESC, id
+    LIMIT 1
+";
+
+fn format_timestamp(datetime: NaiveDateTime) -> String {
+    datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+impl Record {
+    pub fn from_statement(stmt: &sqlite::Statement) -> Result<Self, anyhow::Error> {
+        let id = stmt.read::<i64, _>("id")?;
+        let timestamp: String = stmt.read::<String, _>("timestamp")?;
+        let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")?;
+        let io = IO::try_from(
+            stmt.read::<String, _>("io")?
+            .as_str()
+        )?;
+        let project = stmt.read::<Option<String>, _>("project")?;
+        let tag = stmt.read::<Option<String>, _>("tag")?;
+        let note = stmt.read::<Option<String>, _>("note")?;
+        let correction = stmt.read::<Option<i64>, _>("correction")?;
+        Ok(Self { id, timestamp, io, project, tag, note, correction })
+    }
+}
+
+impl Change {
+    pub fn from_statement(stmt: &sqlite::Statement) -> Result<Self, anyhow::Error> {
+        let entry_id = stmt.read::<i64, _>("entry_id")?;
+        let changed_at = NaiveDateTime::parse_from_str(&stmt.read::<String, _>("changed_at")?, "%Y-%m-%d %H:%M:%S")?;
+        Ok(Self {
+            id: stmt.read::<i64, _>("id")?,
+            changed_at,
+            entry_id,
+            kind: ChangeKind::try_from(stmt.read::<String, _>("kind")?.as_str())?,
+            source: ChangeSource::try_from(stmt.read::<String, _>("source")?.as_str())?,
+            reverts: stmt.read::<Option<i64>, _>("reverts")?,
+            old: read_logged_record(stmt, entry_id, "old")?,
+            new: read_logged_record(stmt, entry_id, "new")?,
+        })
+    }
+}
+
+/// The entry as it was before (`prefix` "old") or after ("new") a change.
+fn read_logged_record(stmt: &sqlite::Statement, id: i64, prefix: &str) -> Result<Option<Record>, anyhow::Error> {
+    let Some(timestamp) = stmt.read::<Option<String>, _>(format!("{prefix}_timestamp").as_str())? else {
+        return Ok(None);
+    };
+    Ok(Some(Record {
+        id,
+        timestamp: NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")?,
+        io: IO::try_from(stmt.read::<String, _>(format!("{prefix}_io").as_str())?.as_str())?,
+        project: stmt.read::<Option<String>, _>(format!("{prefix}_project").as_str())?,
+        tag: stmt.read::<Option<String>, _>(format!("{prefix}_tag").as_str())?,
+        note: stmt.read::<Option<String>, _>(format!("{prefix}_note").as_str())?,
+        correction: stmt.read::<Option<i64>, _>(format!("{prefix}_correction").as_str())?,
+    }))
+}
+
+/// Reads every row `stmt` returns.
+fn read_records(mut stmt: sqlite::Statement) -> Result<Vec<Record>, anyhow::Error> {
+    let mut records = vec![];
+    while let State::Row = stmt.next()? {
+        records.push(Record::from_statement(&stmt)?);
+    }
+    Ok(records)
+}
+
+/// Reads the first row `stmt` returns, if any.
+fn read_record(mut stmt: sqlite::Statement) -> Result<Option<Record>, anyhow::Error> {
+    match stmt.next()? {
+        State::Row => Ok(Some(Record::from_statement(&stmt)?)),
+        State::Done => Ok(None),
+    }
+}
+
+fn neighbor(conn: &sqlite::Connection, sql: &str, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+    let mut stmt = conn.prepare(sql)?;
+    stmt.bind::<&[(_, sqlite::Value)]>(&[
+        (":datetime", format_timestamp(datetime).into()),
+        (":exclude", exclude.unwrap_or(-1).into()),
+    ][..])?;
+    read_record(stmt)
+}
+
+/// Makes `edit` and logs it. Must be run inside a transaction.
+fn write_edit(conn: &sqlite::Connection, edit: Edit) -> Result<i64, anyhow::Error> {
+    let old = match edit.entry {
+        Some(id) => conn.get(id)?,
+        None => None,
+    };
+
+    let entry_id = match edit.new {
+        Some(new) => {
+            let mut stmt = conn.prepare(PUT_SQL)?;
+            stmt.bind::<&[(_, sqlite::Value)]>(&[
+                (":id", edit.entry.into()),
+                (":timestamp", format_timestamp(new.timestamp).into()),
+                (":io", new.io.to_string().into()),
+                (":project", new.project.into()),
+                (":tag", new.tag.into()),
+                (":note", new.note.into()),
+                (":correction", new.correction.into()),
+            ][..])?;
+            stmt.next()?;
+            match edit.entry {
+                Some(id) => id,
+                None => {
+                    let mut stmt = conn.prepare("SELECT last_insert_rowid()")?;
+                    stmt.next()?;
+                    stmt.read::<i64, _>(0)?
+                },
+            }
+        },
+        None => {
+            let id = edit.entry.ok_or_else(|| anyhow::anyhow!("an edit needs an entry or a new record"))?;
+            let mut stmt = conn.prepare(DELETE_SQL)?;
+            stmt.bind::<&[(_, sqlite::Value)]>(&[
+                (":deleted_at", format_timestamp(chrono::Local::now().naive_local()).into()),
+                (":id", id.into()),
+            ][..])?;
+            stmt.next()?;
+            id
+        },
+    };
+
+    let new = conn.get(entry_id)?;
+    let kind = match (&old, &new) {
+        (None, _) => ChangeKind::Insert,
+        (_, None) => ChangeKind::Delete,
+        _ => ChangeKind::Update,
+    };
+    log_change(conn, entry_id, kind, edit.source, edit.reverts, &old, &new)?;
+
+    Ok(entry_id)
+}
+
+/// Adds a change to entry `entry_id` to the change log.
+fn log_change(conn: &sqlite::Connection, entry_id: i64, kind: ChangeKind, source: ChangeSource, reverts: Option<i64>, old: &Option<Record>, new: &Option<Record>) -> Result<(), anyhow::Error> {
+    let logged = |record: &Option<Record>| record.as_ref().map_or((None, None, None, None, None, None), |record| (
+        Some(format_timestamp(record.timestamp)),
+        Some(record.io.to_string()),
+        record.project.clone(),
+        record.tag.clone(),
+        record.note.clone(),
+        record.correction,
+    ));
+    let (old_timestamp, old_io, old_project, old_tag, old_note, old_correction) = logged(old);
+    let (new_timestamp, new_io, new_project, new_tag, new_note, new_correction) = logged(new);
+
+    let mut stmt = conn.prepare(LOG_SQL)?;
+    stmt.bind::<&[(_, sqlite::Value)]>(&[
+        (":changed_at", format_timestamp(chrono::Local::now().naive_local()).into()),
+        (":entry_id", entry_id.into()),
+        (":kind", kind.as_str().into()),
+        (":source", source.as_str().into()),
+        (":reverts", reverts.into()),
+        (":old_timestamp", old_timestamp.into()),
+        (":old_io", old_io.into()),
+        (":old_project", old_project.into()),
+        (":old_tag", old_tag.into()),
+        (":old_note", old_note.into()),
+        (":old_correction", old_correction.into()),
+        (":new_timestamp", new_timestamp.into()),
+        (":new_io", new_io.into()),
+        (":new_project", new_project.into()),
+        (":new_tag", new_tag.into()),
+        (":new_note", new_note.into()),
+        (":new_correction", new_correction.into()),
+    ][..])?;
+    stmt.next()?;
+    Ok(())
+}
+
+/// Removes entries deleted before `before` and logs them. Must be run inside
+/// a transaction.
+fn purge_before(conn: &sqlite::Connection, before: NaiveDateTime) -> Result<usize, anyhow::Error> {
+    let mut stmt = conn.prepare(PURGEABLE_SQL)?;
+    stmt.bind((1, format_timestamp(before).as_str()))?;
+    let purged = read_records(stmt)?;
+    for record in purged.iter() {
+        let mut stmt = conn.prepare(PURGE_SQL)?;
+        stmt.bind((1, record.id))?;
+        stmt.next()?;
+        log_change(conn, record.id, ChangeKind::Purge, ChangeSource::Edit, None, &Some(record.clone()), &None)?;
+    }
+    Ok(purged.len())
+}
+
+impl Storage for sqlite::Connection {
+    fn write_all(&self, edits: Vec<Edit>) -> Result<Vec<i64>, anyhow::Error> {
+        // A savepoint works inside or outside of another transaction
+        self.execute("SAVEPOINT write_edit")?;
+        match edits.into_iter().map(|edit| write_edit(self, edit)).collect() {
+            Ok(ids) => {
+                self.execute("RELEASE write_edit")?;
+                Ok(ids)
+            },
+            Err(err) => {
+                self.execute("ROLLBACK TO write_edit; RELEASE write_edit")?;
+                Err(err)
+            },
+        }
+    }
+
+    fn trash(&self) -> Result<Vec<Trashed>, anyhow::Error> {
+        let mut stmt = self.prepare(TRASH_SQL)?;
+        let mut trash = vec![];
+        while let State::Row = stmt.next()? {
+            let deleted_at = stmt.read::<String, _>("deleted_at")?;
+            trash.push(Trashed {
+                record: Record::from_statement(&stmt)?,
+                deleted_at: NaiveDateTime::parse_from_str(&deleted_at, "%Y-%m-%d %H:%M:%S")?,
+            });
+        }
+        Ok(trash)
+    }
+
+    fn purge(&self, before: NaiveDateTime) -> Result<usize, anyhow::Error> {
+        self.execute("SAVEPOINT purge")?;
+        match purge_before(self, before) {
+            Ok(count) => {
+                self.execute("RELEASE purge")?;
+                Ok(count)
+            },
+            Err(err) => {
+                self.execute("ROLLBACK TO purge; RELEASE purge")?;
+                Err(err)
+            },
+        }
+    }
+
+    fn changes(&self) -> Result<Vec<Change>, anyhow::Error> {
+        let mut stmt = self.prepare(CHANGES_SQL)?;
+        let mut changes = vec![];
+        while let State::Row = stmt.next()? {
+            changes.push(Change::from_statement(&stmt)?);
+        }
+        Ok(changes)
+    }
+
+    fn get(&self, id: i64) -> Result<Option<Record>, anyhow::Error> {
+        let mut stmt = self.prepare(GET_SQL)?;
+        stmt.bind((1, id))?;
+        read_record(stmt)
+    }
+
+    fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>, anyhow::Error> {
+        let mut stmt = self.prepare(RANGE_SQL)?;
+        stmt.bind::<&[(_, sqlite::Value)]>(&[
+            (":from", from.format("%Y-%m-%d").to_string().into()),
+            (":to", to.format("%Y-%m-%d").to_string().into()),
+        ][..])?;
+        read_records(stmt)
+    }
+
+    fn last_until(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+        neighbor(self, LAST_UNTIL_SQL, datetime, exclude)
+    }
+
+    fn first_after(&self, datetime: NaiveDateTime, exclude: Option<i64>) -> Result<Option<Record>, anyhow::Error> {
+        neighbor(self, FIRST_AFTER_SQL, datetime, exclude)
+    }
+}
@@ -0,0 +1,588 @@
+//! Parsing for the `--time` and `--date` arguments.
+//!
+//! Dates can be written as `2023-05-31`, `today`, `yesterday`, `tomorrow`,
+//! a weekday (`monday`, `mon`: the most recent one, today included),
+//! `last monday` (the most recent one before today), `3 days ago`, `-3d`,
+//! `2 weeks ago` or `-2w`.
+//!
+//! Times can be a time of day (`8:00`, `17:30:15`, `5pm`, `5:30 pm`, `noon`,
+//! `midnight`), a time of day on a day (`yesterday 17:30`, `last friday 5pm`,
+//! `2023-05-31 8:00`), or relative to the current time (`now`, `-15m`,
+//! `-1h30m`, `15 minutes ago`, `an hour ago`).
+//!
+//! Ages, like `--older-than`, are amounts of time: `30d`, `2 weeks`, `12h`,
+//! `15m`, `5s`.
+//!
+//! Time ranges, like the interval given to `timest add`, are two times of day
+//! joined by a dash: `9:00-10:30`, `9am-5pm`.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::str::FromStr;
+
+/// A day, possibly relative to today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateExpr {
+    Date(NaiveDate),
+    /// Today is 0 days ago, yesterday 1, tomorrow -1
+    DaysAgo(i64),
+    /// The most recent `weekday`, today included unless `before_today`
+    Weekday { weekday: Weekday, before_today: bool },
+}
+
+impl DateExpr {
+    pub fn resolve(&self, today: NaiveDate) -> Result<NaiveDate, anyhow::Error> {
+        let days_back = match *self {
+            Self::Date(date) => return Ok(date),
+            Self::DaysAgo(days) => days,
+            Self::Weekday { weekday, before_today } => {
+                let mut days_back = (7 + today.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday()) % 7;
+                if days_back == 0 && before_today {
+                    days_back = 7;
+                }
+                days_back as i64
+            }
+        };
+        Duration::try_days(days_back)
+            .and_then(|back| today.checked_sub_signed(back))
+            .ok_or_else(|| anyhow::anyhow!("{} days from {today} is too far to be a date", -days_back))
+    }
+}
+
+impl FromStr for DateExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase();
+        let words: Vec<&str> = normalized.split_whitespace().collect();
+        parse_date_words(&words)
+            .ok_or_else(|| anyhow::anyhow!(
+                "`{s}` is not a date. Try 2023-05-31, today, yesterday, monday, last monday or 3 days ago"
+            ))
+    }
+}
+
+fn parse_date_words(words: &[&str]) -> Option<DateExpr> {
+    match words {
+        [word] => {
+            if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+                return Some(DateExpr::Date(date));
+            }
+            match *word {
+                "today" => Some(DateExpr::DaysAgo(0)),
+                "yesterday" => Some(DateExpr::DaysAgo(1)),
+                "tomorrow" => Some(DateExpr::DaysAgo(-1)),
+                _ => {
+                    if let Ok(weekday) = Weekday::from_str(word) {
+                        return Some(DateExpr::Weekday { weekday, before_today: false });
+                    }
+                    let days = parse_signed_offset(word, &[("d", 1), ("w", 7)])?;
+                    Some(DateExpr::DaysAgo(-days))
+                }
+            }
+        },
+        ["last", weekday] => Some(DateExpr::Weekday {
+            weekday: Weekday::from_str(weekday).ok()?,
+            before_today: true,
+        }),
+        [amount @ .., "ago"] => {
+            let days = parse_amount_words(amount, &[
+                (&["d", "day", "days"], 1),
+                (&["w", "wk", "week", "weeks"], 7),
+            ])?;
+            Some(DateExpr::DaysAgo(days))
+        },
+        _ => None,
+    }
+}
+
+/// A `--time` argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeExpr {
+    /// A time of day, on `date` if given
+    At { date: Option<DateExpr>, time: NaiveTime },
+    /// An offset from the current time. `now` is an offset of zero
+    Relative(Duration),
+}
+
+impl TimeExpr {
+    /// Whether this depends on the system clock being right.
+    pub fn is_relative(&self) -> bool {
+        matches!(self, Self::Relative(_))
+    }
+
+    /// The moment this refers to. `date` comes from a separate `--date`
+    /// argument, and can't be combined with times that already say the day.
+    pub fn resolve(&self, now: NaiveDateTime, date: Option<NaiveDate>) -> Result<NaiveDateTime, anyhow::Error> {
+        match (*self, date) {
+            (Self::At { date: None, time }, date) => Ok(date.unwrap_or(now.date()).and_time(time)),
+            (Self::At { date: Some(day), time }, None) => Ok(day.resolve(now.date())?.and_time(time)),
+            (Self::Relative(offset), None) => now.checked_add_signed(offset)
+                .ok_or_else(|| anyhow::anyhow!("{} seconds from {} is too far to be a time", offset.num_seconds(), now.format("%Y-%m-%d %H:%M:%S"))),
+            (_, Some(_)) => Err(anyhow::anyhow!(
+                "--time already says which day it is, so it can't be used with --date"
+            )),
+        }
+    }
+}
+
+/// The moment given by `--time` and `--date`, defaulting to `now`.
+pub fn resolve_datetime(time: Option<TimeExpr>, date: Option<DateExpr>, now: NaiveDateTime) -> Result<NaiveDateTime, anyhow::Error> {
+    let date = date.map(|date| date.resolve(now.date())).transpose()?;
+    match time {
+        Some(time) => time.resolve(now, date),
+        None => Ok(date.unwrap_or(now.date()).and_time(now.time())),
+    }
+}
+
+/// The new moment for an entry at `old`, given by `--time` and `--date`.
+/// Whatever they leave out is kept from `old` rather than taken from `now`.
+pub fn amend_datetime(time: Option<TimeExpr>, date: Option<DateExpr>, old: NaiveDateTime, now: NaiveDateTime) -> Result<NaiveDateTime, anyhow::Error> {
+    let date = date.map(|date| date.resolve(now.date())).transpose()?;
+    match time {
+        None => Ok(date.unwrap_or(old.date()).and_time(old.time())),
+        Some(TimeExpr::At { date: None, time }) => Ok(date.unwrap_or(old.date()).and_time(time)),
+        Some(time) => time.resolve(now, date),
+    }
+}
+
+impl FromStr for TimeExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim()
+            .to_lowercase()
+            .replace(" am", "am")
+            .replace(" pm", "pm");
+        let words: Vec<&str> = normalized.split_whitespace().collect();
+        parse_time_words(&words)
+            .ok_or_else(|| anyhow::anyhow!(
+                "`{s}` is not a time. Try 8:00, 17:30:15, 5pm, now, -15m, 15 minutes ago or yesterday 17:30"
+            ))
+    }
+}
+
+/// A length of time, e.g. how long something has been in the trash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age(pub Duration);
+
+/// Units of an [`Age`], largest first, with their length in seconds.
+const AGE_UNITS: &[(&[&str], i64)] = &[
+    (&["w", "week", "weeks"], 7 * 86400),
+    (&["d", "day", "days"], 86400),
+    (&["h", "hour", "hours"], 3600),
+    (&["m", "min", "mins", "minute", "minutes"], 60),
+    (&["s", "sec", "secs", "second", "seconds"], 1),
+];
+
+impl std::fmt::Display for Age {
+    /// In the largest unit that fits exactly, e.g. `15m` or `30d`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.0.num_seconds();
+        let (names, scale) = AGE_UNITS.iter()
+            .find(|(_, scale)| seconds % scale == 0 && seconds != 0)
+            .unwrap_or(&AGE_UNITS[AGE_UNITS.len() - 1]);
+        write!(f, "{}{}", seconds / scale, names[0])
+    }
+}
+
+impl FromStr for Age {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase();
+        let words: Vec<&str> = normalized.split_whitespace().collect();
+        parse_amount_words(&words, AGE_UNITS)
+            .and_then(Duration::try_seconds)
+            .map(Age)
+            .ok_or_else(|| anyhow::anyhow!("`{s}` is not an amount of time. Try 30d, 2 weeks, 12h or 15m"))
+    }
+}
+
+/// The start and end of an interval on one day, e.g. `9:00-10:30`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl FromStr for TimeRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_lowercase().replace(' ', "");
+        let range = normalized.split_once('-')
+            .and_then(|(start, end)| Some(TimeRange {
+                start: parse_time_of_day(start)?,
+                end: parse_time_of_day(end)?,
+            }))
+            .ok_or_else(|| anyhow::anyhow!("`{s}` is not a time range. Try 9:00-10:30 or 9am-5pm"))?;
+        if range.end <= range.start {
+            return Err(anyhow::anyhow!("`{s}` ends before it starts"));
+        }
+        Ok(range)
+    }
+}
+
+fn parse_time_words(words: &[&str]) -> Option<TimeExpr> {
+    match words {
+        [] => None,
+        ["now"] => Some(TimeExpr::Relative(Duration::zero())),
+        [word] if word.starts_with(['-', '+']) => {
+            let seconds = parse_signed_offset(word, &[("h", 3600), ("m", 60), ("s", 1)])?;
+            Some(TimeExpr::Relative(Duration::try_seconds(seconds)?))
+        },
+        [amount @ .., "ago"] => {
+            let seconds = parse_amount_words(amount, &[
+                (&["h", "hr", "hrs", "hour", "hours"], 3600),
+                (&["m", "min", "mins", "minute", "minutes"], 60),
+                (&["s", "sec", "secs", "second", "seconds"], 1),
+            ])?;
+            Some(TimeExpr::Relative(-Duration::try_seconds(seconds)?))
+        },
+        [word] => Some(TimeExpr::At { date: None, time: parse_time_of_day(word)? }),
+        // A day, then a time of day, or the other way around
+        [first, .., last] => day_and_time(&words[..words.len() - 1], last)
+            .or_else(|| day_and_time(&words[1..], first)),
+    }
+}
+
+fn day_and_time(day: &[&str], time: &str) -> Option<TimeExpr> {
+    Some(TimeExpr::At {
+        date: Some(parse_date_words(day)?),
+        time: parse_time_of_day(time)?,
+    })
+}
+
+/// `8`, `8:00`, `17:30:15`, `5pm`, `5:30pm`, `noon` or `midnight`.
+fn parse_time_of_day(word: &str) -> Option<NaiveTime> {
+    match word {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {},
+    }
+
+    let (clock, meridiem) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (word, None)
+    };
+
+    let mut parts = clock.split(':');
+    let hour: u32 = parse_digits(parts.next()?, 2)?;
+    let minute: u32 = parts.next().map_or(Some(0), |minute| parse_exact_digits(minute, 2))?;
+    let second: u32 = parts.next().map_or(Some(0), |second| parse_exact_digits(second, 2))?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let hour = match meridiem {
+        None => hour,
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+/// `-15m`, `+1h30m`, `-3d`: a sign, then numbers each followed by one of `units`.
+/// Returns the total in the units' base (e.g. seconds, days).
+fn parse_signed_offset(word: &str, units: &[(&str, i64)]) -> Option<i64> {
+    let (sign, mut rest) = if let Some(rest) = word.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        (1, word.strip_prefix('+')?)
+    };
+
+    let mut total = 0_i64;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let amount: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let (unit, scale) = units.iter().find(|(unit, _)| rest.starts_with(unit))?;
+        total = total.checked_add(amount.checked_mul(*scale)?)?;
+        rest = &rest[unit.len()..];
+    }
+    (total != 0 || word.len() > 1).then_some(sign * total)
+}
+
+/// `15 minutes`, `1 hour 30 minutes`, `an hour`, `15m`: amounts followed by
+/// one of the `units`' names. Returns the total in the units' base.
+fn parse_amount_words(words: &[&str], units: &[(&[&str], i64)]) -> Option<i64> {
+    let scale_of = |name: &str| units.iter()
+        .find(|(names, _)| names.contains(&name))
+        .map(|(_, scale)| *scale);
+
+    if words.is_empty() {
+        return None;
+    }
+    let mut total = 0_i64;
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let amount: i64 = match *word {
+            "a" | "an" => 1,
+            // Only digits, so amounts can't be negative
+            word if word.chars().all(|c| c.is_ascii_digit()) => word.parse().ok()?,
+            // Compact form, e.g. `15m`
+            word => {
+                let digits = word.find(|c: char| !c.is_ascii_digit())?;
+                let amount: i64 = word[..digits].parse().ok()?;
+                total = total.checked_add(amount.checked_mul(scale_of(&word[digits..])?)?)?;
+                continue;
+            },
+        };
+        let scale = scale_of(words.next()?)?;
+        total = total.checked_add(amount.checked_mul(scale)?)?;
+    }
+    Some(total)
+}
+
+fn parse_digits(digits: &str, max_len: usize) -> Option<u32> {
+    if digits.is_empty() || digits.len() > max_len || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn parse_exact_digits(digits: &str, len: usize) -> Option<u32> {
+    (digits.len() == len).then_some(())?;
+    parse_digits(digits, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 5, 31).unwrap()
+    }
+
+    fn now() -> NaiveDateTime {
+        today().and_hms_opt(14, 45, 30).unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse::<DateExpr>().unwrap().resolve(today()).unwrap()
+    }
+
+    fn time(s: &str) -> NaiveDateTime {
+        s.parse::<TimeExpr>().unwrap().resolve(now(), None).unwrap()
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn at(d: NaiveDate, h: u32, m: u32, s: u32) -> NaiveDateTime {
+        d.and_hms_opt(h, m, s).unwrap()
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(date("2023-01-15"), ymd(2023, 1, 15));
+        assert_eq!(date(" 2024-02-29 "), ymd(2024, 2, 29));
+        assert!("2023-02-29".parse::<DateExpr>().is_err());
+        assert!("2023-13-01".parse::<DateExpr>().is_err());
+    }
+
+    #[test]
+    fn named_days() {
+        assert_eq!(date("today"), today());
+        assert_eq!(date("Yesterday"), ymd(2023, 5, 30));
+        assert_eq!(date("tomorrow"), ymd(2023, 6, 1));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(date("monday"), ymd(2023, 5, 29));
+        assert_eq!(date("mon"), ymd(2023, 5, 29));
+        assert_eq!(date("wednesday"), today());
+        assert_eq!(date("thursday"), ymd(2023, 5, 25));
+        assert_eq!(date("last monday"), ymd(2023, 5, 29));
+        assert_eq!(date("last wednesday"), ymd(2023, 5, 24));
+        assert_eq!(date("Last Sun"), ymd(2023, 5, 28));
+        assert!("last".parse::<DateExpr>().is_err());
+        assert!("last funday".parse::<DateExpr>().is_err());
+    }
+
+    #[test]
+    fn days_ago() {
+        assert_eq!(date("3 days ago"), ymd(2023, 5, 28));
+        assert_eq!(date("1 day ago"), ymd(2023, 5, 30));
+        assert_eq!(date("a week ago"), ymd(2023, 5, 24));
+        assert_eq!(date("2 weeks ago"), ymd(2023, 5, 17));
+        assert_eq!(date("3d ago"), ymd(2023, 5, 28));
+        assert_eq!(date("-3d"), ymd(2023, 5, 28));
+        assert_eq!(date("-1w"), ymd(2023, 5, 24));
+        assert_eq!(date("+1d"), ymd(2023, 6, 1));
+        assert_eq!(date("-31d"), ymd(2023, 4, 30));
+        assert!("-3".parse::<DateExpr>().is_err());
+        assert!("-3x".parse::<DateExpr>().is_err());
+        assert!("ago".parse::<DateExpr>().is_err());
+        assert!("3 fortnights ago".parse::<DateExpr>().is_err());
+        assert!("-3 days ago".parse::<DateExpr>().is_err());
+        assert!("-99999999999d".parse::<DateExpr>().unwrap().resolve(today()).is_err());
+        assert!("99999999999999999 weeks ago".parse::<DateExpr>().unwrap().resolve(today()).is_err());
+    }
+
+    #[test]
+    fn bad_dates() {
+        assert!("".parse::<DateExpr>().is_err());
+        assert!("someday".parse::<DateExpr>().is_err());
+        assert!("8:00".parse::<DateExpr>().is_err());
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(time("8:00"), at(today(), 8, 0, 0));
+        assert_eq!(time("08:00"), at(today(), 8, 0, 0));
+        assert_eq!(time("8"), at(today(), 8, 0, 0));
+        assert_eq!(time("17:30:15"), at(today(), 17, 30, 15));
+        assert_eq!(time("23:59:59"), at(today(), 23, 59, 59));
+        assert_eq!(time("noon"), at(today(), 12, 0, 0));
+        assert_eq!(time("midnight"), at(today(), 0, 0, 0));
+        assert!("24:00".parse::<TimeExpr>().is_err());
+        assert!("8:60".parse::<TimeExpr>().is_err());
+        assert!("8:5".parse::<TimeExpr>().is_err());
+        assert!("8:00:00:00".parse::<TimeExpr>().is_err());
+        assert!("8:".parse::<TimeExpr>().is_err());
+        assert!(":30".parse::<TimeExpr>().is_err());
+    }
+
+    #[test]
+    fn am_pm() {
+        assert_eq!(time("5pm"), at(today(), 17, 0, 0));
+        assert_eq!(time("5PM"), at(today(), 17, 0, 0));
+        assert_eq!(time("5 pm"), at(today(), 17, 0, 0));
+        assert_eq!(time("5:30pm"), at(today(), 17, 30, 0));
+        assert_eq!(time("9am"), at(today(), 9, 0, 0));
+        assert_eq!(time("12am"), at(today(), 0, 0, 0));
+        assert_eq!(time("12pm"), at(today(), 12, 0, 0));
+        assert_eq!(time("12:15am"), at(today(), 0, 15, 0));
+        assert!("0am".parse::<TimeExpr>().is_err());
+        assert!("13pm".parse::<TimeExpr>().is_err());
+        assert!("pm".parse::<TimeExpr>().is_err());
+    }
+
+    #[test]
+    fn now_and_offsets() {
+        assert_eq!(time("now"), now());
+        assert_eq!(time("NOW"), now());
+        assert_eq!(time("-15m"), at(today(), 14, 30, 30));
+        assert_eq!(time("-2h"), at(today(), 12, 45, 30));
+        assert_eq!(time("-1h30m"), at(today(), 13, 15, 30));
+        assert_eq!(time("-30s"), at(today(), 14, 45, 0));
+        assert_eq!(time("+10m"), at(today(), 14, 55, 30));
+        assert_eq!(time("-15h"), at(ymd(2023, 5, 30), 23, 45, 30));
+        assert!("-".parse::<TimeExpr>().is_err());
+        assert!("-15".parse::<TimeExpr>().is_err());
+        assert!("-15x".parse::<TimeExpr>().is_err());
+        assert!("-m".parse::<TimeExpr>().is_err());
+        assert!("-1d".parse::<TimeExpr>().is_err());
+        assert!("-9999999999999999h".parse::<TimeExpr>().is_err());
+        assert!("-9999999999h".parse::<TimeExpr>().unwrap().resolve(now(), None).is_err());
+    }
+
+    #[test]
+    fn ago() {
+        assert_eq!(time("15 minutes ago"), at(today(), 14, 30, 30));
+        assert_eq!(time("1 minute ago"), at(today(), 14, 44, 30));
+        assert_eq!(time("an hour ago"), at(today(), 13, 45, 30));
+        assert_eq!(time("a minute ago"), at(today(), 14, 44, 30));
+        assert_eq!(time("2 hours ago"), at(today(), 12, 45, 30));
+        assert_eq!(time("1 hour 30 minutes ago"), at(today(), 13, 15, 30));
+        assert_eq!(time("15m ago"), at(today(), 14, 30, 30));
+        assert_eq!(time("10 secs ago"), at(today(), 14, 45, 20));
+        assert!("ago".parse::<TimeExpr>().is_err());
+        assert!("15 ago".parse::<TimeExpr>().is_err());
+        assert!("minutes ago".parse::<TimeExpr>().is_err());
+        assert!("15 parsecs ago".parse::<TimeExpr>().is_err());
+    }
+
+    #[test]
+    fn day_and_time() {
+        assert_eq!(time("yesterday 17:30"), at(ymd(2023, 5, 30), 17, 30, 0));
+        assert_eq!(time("yesterday 5pm"), at(ymd(2023, 5, 30), 17, 0, 0));
+        assert_eq!(time("yesterday 5 pm"), at(ymd(2023, 5, 30), 17, 0, 0));
+        assert_eq!(time("5pm yesterday"), at(ymd(2023, 5, 30), 17, 0, 0));
+        assert_eq!(time("last friday 9am"), at(ymd(2023, 5, 26), 9, 0, 0));
+        assert_eq!(time("monday noon"), at(ymd(2023, 5, 29), 12, 0, 0));
+        assert_eq!(time("2023-05-01 8:00"), at(ymd(2023, 5, 1), 8, 0, 0));
+        assert_eq!(time("2 days ago 8:00"), at(ymd(2023, 5, 29), 8, 0, 0));
+        assert!("yesterday".parse::<TimeExpr>().is_err());
+        assert!("yesterday later".parse::<TimeExpr>().is_err());
+        assert!("someday 5pm".parse::<TimeExpr>().is_err());
+    }
+
+    #[test]
+    fn with_date_argument() {
+        let may_1 = Some(ymd(2023, 5, 1));
+        let resolve = |s: &str| s.parse::<TimeExpr>().unwrap().resolve(now(), may_1);
+        assert_eq!(resolve("8:00").unwrap(), at(ymd(2023, 5, 1), 8, 0, 0));
+        assert_eq!(resolve("5pm").unwrap(), at(ymd(2023, 5, 1), 17, 0, 0));
+        assert!(resolve("yesterday 8:00").is_err());
+        assert!(resolve("now").is_err());
+        assert!(resolve("-15m").is_err());
+    }
+
+    #[test]
+    fn relative_times() {
+        assert!("now".parse::<TimeExpr>().unwrap().is_relative());
+        assert!("-15m".parse::<TimeExpr>().unwrap().is_relative());
+        assert!("15 minutes ago".parse::<TimeExpr>().unwrap().is_relative());
+        assert!(!"8:00".parse::<TimeExpr>().unwrap().is_relative());
+        assert!(!"yesterday 8:00".parse::<TimeExpr>().unwrap().is_relative());
+    }
+
+    #[test]
+    fn amending_keeps_what_is_left_out() {
+        let old = at(ymd(2023, 5, 23), 8, 0, 0);
+        let amend = |time: Option<&str>, date: Option<&str>| amend_datetime(
+            time.map(|time| time.parse().unwrap()),
+            date.map(|date| date.parse().unwrap()),
+            old,
+            now(),
+        ).unwrap();
+        assert_eq!(amend(None, None), old);
+        assert_eq!(amend(Some("8:30"), None), at(ymd(2023, 5, 23), 8, 30, 0));
+        assert_eq!(amend(None, Some("2023-05-24")), at(ymd(2023, 5, 24), 8, 0, 0));
+        assert_eq!(amend(Some("9am"), Some("yesterday")), at(ymd(2023, 5, 30), 9, 0, 0));
+        assert_eq!(amend(Some("yesterday 9am"), None), at(ymd(2023, 5, 30), 9, 0, 0));
+        assert_eq!(amend(Some("-15m"), None), at(today(), 14, 30, 30));
+    }
+
+    #[test]
+    fn ages() {
+        let age = |s: &str| s.parse::<Age>().unwrap().0;
+        assert_eq!(age("30d"), Duration::days(30));
+        assert_eq!(age("2 weeks"), Duration::days(14));
+        assert_eq!(age("1w 2d"), Duration::days(9));
+        assert_eq!(age("12h"), Duration::hours(12));
+        assert!("soon".parse::<Age>().is_err());
+        assert_eq!(age("15m"), Duration::minutes(15));
+        assert_eq!(age("1h 30 minutes"), Duration::minutes(90));
+        assert!("30".parse::<Age>().is_err());
+        assert!("-5 minutes".parse::<Age>().is_err());
+        assert!("+5 minutes".parse::<Age>().is_err());
+        assert!("99999999999999999s".parse::<Age>().is_err());
+        assert_eq!(Age(Duration::minutes(15)).to_string(), "15m");
+        assert_eq!(Age(Duration::days(14)).to_string(), "2w");
+        assert_eq!(Age(Duration::seconds(90)).to_string(), "90s");
+        assert_eq!(Age(Duration::zero()).to_string(), "0s");
+        assert_eq!(age("0s"), Duration::zero());
+    }
+
+    #[test]
+    fn time_ranges() {
+        let range = |s: &str| s.parse::<TimeRange>().map(|range| (range.start, range.end));
+        let hm = |h: u32, m: u32| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert_eq!(range("09:00-10:30").unwrap(), (hm(9, 0), hm(10, 30)));
+        assert_eq!(range("9am - 5pm").unwrap(), (hm(9, 0), hm(17, 0)));
+        assert!(range("10:30-9:00").is_err());
+        assert!(range("9:00-9:00").is_err());
+        assert!(range("9:00").is_err());
+        assert!(range("-15m").is_err());
+    }
+}
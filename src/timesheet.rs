@@ -0,0 +1,107 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::path::Path;
+
+use crate::{Config, IO, OvernightRule, clock, migrations, sequence};
+use crate::storage::Storage;
+use crate::report::interval::{self, Interval, Record};
+
+/// A timesheet database. Opening one brings its schema up to date and loads
+/// its settings.
+///
+/// ```no_run
+/// use timest::{IO, Timesheet};
+///
+/// let timesheet = Timesheet::open("timest.db3")?;
+/// let now = chrono::Local::now().naive_local();
+/// timesheet.clock(now, IO::I, Some("acme"), None, None)?;
+/// let worked = timesheet.seconds_worked(now.date(), now.date())?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Timesheet {
+    conn: sqlite::Connection,
+    config: Config,
+}
+
+impl Timesheet {
+    /// Opens the database at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let conn = sqlite::open(path)?;
+        migrations::migrate(&conn, path)?;
+        let config = Config::load(&conn)?;
+        Ok(Self { conn, config })
+    }
+
+    pub(crate) fn connection(&self) -> &sqlite::Connection {
+        &self.conn
+    }
+
+    /// The entries, without the settings.
+    pub fn storage(&self) -> &dyn Storage {
+        &self.conn
+    }
+
+    /// Settings as they were when the timesheet was opened.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Makes every question fail as if nobody answered it, for `--no-input`.
+    pub fn disable_input(&mut self) {
+        self.config.no_input = true;
+    }
+
+    /// Adds a clock in or out at `datetime` and returns its id. Does not check
+    /// the in/out sequence; see [`Timesheet::sequence_problems`].
+    pub fn clock(&self, datetime: NaiveDateTime, io: IO, project: Option<&str>, tag: Option<&str>, note: Option<&str>) -> Result<i64, anyhow::Error> {
+        clock::add_clock(&self.conn, datetime, io, project.map(str::to_owned), tag.map(str::to_owned), note.map(str::to_owned), None)
+    }
+
+    /// Whether a clock at `datetime` should be a clock in or a clock out,
+    /// judging by the entries around it.
+    pub fn infer_io(&self, datetime: NaiveDateTime) -> Result<IO, anyhow::Error> {
+        clock::infer_io(&self.conn, datetime)
+    }
+
+    /// Ways that a new clock `io` at `datetime` would break the in/out sequence.
+    pub fn sequence_problems(&self, datetime: NaiveDateTime, io: IO) -> Result<Vec<String>, anyhow::Error> {
+        sequence::sequence_problems(&self.conn, datetime, io, None)
+    }
+
+    /// Entries from `from` through `to`, oldest first.
+    pub fn records(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>, anyhow::Error> {
+        interval::get_records(&self.conn, from, to)
+    }
+
+    /// Intervals touching the days `from` through `to`. Intervals that cross
+    /// midnight are left whole.
+    pub fn intervals(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Interval>, anyhow::Error> {
+        interval::get_intervals(&self.conn, from, to)
+    }
+
+    /// Intervals counted towards `date` under the `overnight` setting.
+    pub fn day_intervals(&self, date: NaiveDate) -> Result<Vec<Interval>, anyhow::Error> {
+        self.day_intervals_with(date, self.config.overnight)
+    }
+
+    /// Intervals counted towards `date` under `rule`.
+    pub fn day_intervals_with(&self, date: NaiveDate, rule: OvernightRule) -> Result<Vec<Interval>, anyhow::Error> {
+        let intervals = self.intervals(date, date)?;
+        Ok(interval::intervals_for_day(&intervals, date, rule))
+    }
+
+    /// Seconds worked from `from` through `to`, or `None` if any of those
+    /// days has an incomplete interval.
+    pub fn seconds_worked(&self, from: NaiveDate, to: NaiveDate) -> Result<Option<i64>, anyhow::Error> {
+        let intervals = self.intervals(from, to)?;
+        let mut total = 0;
+        for date in from.iter_days().take_while(|date| date <= &to) {
+            let day = interval::intervals_for_day(&intervals, date, self.config.overnight);
+            match interval::seconds_worked(&day) {
+                Some(seconds) => total += seconds,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(total))
+    }
+}
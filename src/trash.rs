@@ -0,0 +1,47 @@
+use crate::{Error, Timesheet, TrashAction, check_time::confirm};
+use chrono::Timelike;
+
+pub fn trash_cmd(timesheet: &Timesheet, action: TrashAction) -> Result<(), anyhow::Error> {
+    let storage = timesheet.storage();
+    match action {
+        TrashAction::List => {
+            let trash = storage.trash()?;
+            if trash.is_empty() {
+                println!("The trash is empty");
+            }
+            for trashed in trash {
+                println!("{}  (deleted {})", trashed.record, trashed.deleted_at.format("%Y-%m-%d %H:%M:%S"));
+            }
+        },
+        TrashAction::Restore { id } => {
+            if !storage.restore(id)? {
+                if storage.get(id)?.is_some() {
+                    anyhow::bail!("entry {id} is not in the trash");
+                }
+                return Err(Error::BadId(id).into());
+            }
+            if let Some(record) = storage.get(id)? {
+                println!("restored: {record}");
+            }
+        },
+        TrashAction::Purge { older_than, yes } => {
+            let before = chrono::Local::now().naive_local().checked_sub_signed(older_than.0)
+                .ok_or_else(|| anyhow::anyhow!("nothing can be deleted more than {older_than} ago"))?;
+            // Deletion times are stored to the second
+            let before = before.with_nanosecond(0).expect("0 is a valid nanosecond");
+            let count = storage.trash()?.iter()
+                .filter(|trashed| trashed.deleted_at < before)
+                .count();
+            if count == 0 {
+                println!("Nothing in the trash was deleted before {}", before.format("%Y-%m-%d %H:%M:%S"));
+                return Ok(());
+            }
+            if !yes {
+                confirm(&format!("Permanently remove {count} entries deleted before {}?", before.format("%Y-%m-%d %H:%M:%S")), timesheet.config().no_input)?;
+            }
+            let purged = storage.purge(before)?;
+            println!("Removed {purged} entries for good");
+        },
+    }
+    Ok(())
+}
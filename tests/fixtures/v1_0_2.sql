@@ -0,0 +1,17 @@
+-- A database as left behind by timest 1.0.2
+CREATE TABLE times (
+    id INTEGER PRIMARY KEY NOT NULL,
+    timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    io TEXT NOT NULL CHECK(io in ('i', 'o'))
+);
+
+CREATE TABLE params (
+    parameter TEXT PRIMARY KEY NOT NULL UNIQUE,
+    value TEXT
+) WITHOUT ROWID;
+
+INSERT INTO times (timestamp, io) VALUES
+    ('2023-05-31 08:00:00', 'i'),
+    ('2023-05-31 12:00:00', 'o'),
+    ('2023-05-31 12:30:00', 'i'),
+    ('2023-05-31 17:00:00', 'o');
@@ -1,6 +1,7 @@
 use assert_cmd::Command;
 
-fn get_db_path() -> String {
+/// Each test gets its own database next to `$TIMEST_TEST` so tests can run in parallel.
+fn get_db_path(test_name: &str) -> String {
     let test_db_path = std::env::var("TIMEST_TEST").expect("
         \x1b[0;31m
     The target path for the test database must be specified in the `TIMEST_TEST` 
@@ -11,18 +12,20 @@ fn get_db_path() -> String {
     // Make sure the test db path is valid
     let test_db_pathbuf = std::path::PathBuf::from(&test_db_path);
     assert!(test_db_pathbuf.parent().unwrap().is_dir());
+    let file_name = test_db_pathbuf.file_name().unwrap().to_string_lossy();
+    let test_db_pathbuf = test_db_pathbuf.with_file_name(format!("{test_name}_{file_name}"));
 
     // Clean up db from failed tests
     if test_db_pathbuf.is_file() {
-        std::fs::remove_file(test_db_pathbuf).unwrap();
+        std::fs::remove_file(&test_db_pathbuf).unwrap();
     }
 
-    test_db_path
+    test_db_pathbuf.to_string_lossy().into_owned()
 }
 
-fn new_cmd(test_db_path: &String) -> Command {
+fn new_cmd(test_db_path: &str) -> Command {
     let mut cmd = Command::cargo_bin("timest").unwrap();
-    cmd.args(&["--db-path", test_db_path]);
+    cmd.args(["--db-path", test_db_path]);
     cmd
 }
 
@@ -36,54 +39,53 @@ fn get_output(cmd: &mut Command) -> String {
 
 #[test]
 fn test_1() {
-    let test_db_path = get_db_path();
+    let test_db_path = get_db_path("test_1");
 
     // No arguments
     let mut cmd = Command::cargo_bin("timest").unwrap();
-    cmd.args(&["--db-path", &test_db_path]);
+    cmd.args(["--db-path", &test_db_path]);
     cmd.assert()
         .append_context("main", "no subcommand")
         .failure();
 
     // Clock in
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["clock", "i", "-d", "2023-05-31", "-t", "8:00"]);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "8:00"]);
     cmd.assert()
         .success();
 
     // Read report
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["report", "simple", "-d", "2023-05-31"]);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
     let output = get_output(&mut cmd);
     let last_line = output.trim()
         .split('\n')
-        .last()
+        .next_back()
         .unwrap();
     assert_eq!(last_line,"|  1  |  08:00:00  |  i  |");
 
     // Clock out
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["clock", "o", "--date", "2023-05-31", "--time", "17:00"]);
+    cmd.args(["clock", "o", "--date", "2023-05-31", "--time", "17:00"]);
     cmd.assert().success();
 
     // Read fancy report
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["report", "-d", "2023-05-31"]);
+    cmd.args(["report", "-d", "2023-05-31"]);
     let output = get_output(&mut cmd);
-    let total_time_worked = output.trim()
-        .split_whitespace()
+    let total_time_worked = output.split_whitespace()
         .last()
         .unwrap();
     assert_eq!(total_time_worked, "9:00:00");
 
     // Write incomplete clock in
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["clock", "o", "-d", "2023-05-31", "-t", "7:00"]);
+    cmd.args(["clock", "o", "-d", "2023-05-31", "-t", "7:00", "--force"]);
     cmd.assert().success();
 
     // Read fancy report with error
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["report", "-d", "2023-05-31"]);
+    cmd.args(["report", "-d", "2023-05-31"]);
     let output = get_output(&mut cmd);
     let output = output.trim()
         .split('\n')
@@ -93,33 +95,1227 @@ fn test_1() {
 
     // Move incomplete clock in to end of day
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["fix", "3", "o", "-d", "2023-05-31", "-t", "19:00"]);
+    cmd.args(["fix", "3", "o", "-d", "2023-05-31", "-t", "19:00", "--force", "--yes"]);
     cmd.assert().success();
 
     // Check that move worked
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["report", "simple", "-d", "2023-05-31"]);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
     let output = get_output(&mut cmd);
     let last_line = output.trim()
         .split('\n')
-        .last()
+        .next_back()
         .unwrap();
     assert_eq!(last_line, "|  3  |  19:00:00  |  o  |");
 
     // Delete clock out at 17:00
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["delete", "2"]);
+    cmd.args(["delete", "2", "--yes"]);
     cmd.assert().success();
 
     // Make sure that new TOTAL TIME WORKED has updated
     let mut cmd = new_cmd(&test_db_path);
-    cmd.args(&["report", "-d", "2023-05-31"]);
+    cmd.args(["report", "-d", "2023-05-31"]);
     let output = get_output(&mut cmd);
-    let total_time_worked = output.trim()
-        .split_whitespace()
+    let total_time_worked = output.split_whitespace()
         .last()
         .unwrap();
     assert_eq!(total_time_worked, "11:00:00");
 
     std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
-}
\ No newline at end of file
+}
+#[test]
+fn test_range_reports() {
+    let test_db_path = get_db_path("test_range_reports");
+
+    // Wednesday and Thursday of the same week, plus the following Monday
+    for (io, date, time) in [
+        ("i", "2023-05-31", "8:00"),
+        ("o", "2023-05-31", "12:00"),
+        ("i", "2023-06-01", "9:00"),
+        ("o", "2023-06-01", "10:30"),
+        ("i", "2023-06-05", "8:00"),
+        ("o", "2023-06-05", "9:00"),
+    ] {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", io, "-d", date, "-t", time]);
+        cmd.assert().success();
+    }
+
+    let total_line = |output: String| output.lines()
+        .find(|line| line.starts_with("TOTAL TIME WORKED"))
+        .unwrap()
+        .to_owned();
+
+    // Week containing 2023-06-01 runs from Monday 2023-05-29 to Sunday 2023-06-04
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--week", "-d", "2023-06-01"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("2023-05-29 - 2023-06-04"));
+    assert!(output.contains("│ 2023-05-31 │  Wed  │    4:00:00 │"));
+    assert!(output.contains("│ 2023-06-01 │  Thu  │    1:30:00 │"));
+    assert!(!output.contains("2023-06-05"));
+    assert_eq!(total_line(output), "TOTAL TIME WORKED: 5:30:00");
+
+    // Month of May only sees 2023-05-31
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--month", "-d", "2023-05-15"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("2023-05-01 - 2023-05-31"));
+    assert_eq!(total_line(output), "TOTAL TIME WORKED: 4:00:00");
+
+    // Custom range spanning both weeks
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--from", "2023-05-31", "--to", "2023-06-05"]);
+    let output = get_output(&mut cmd);
+    assert_eq!(total_line(output), "TOTAL TIME WORKED: 6:30:00");
+
+    // Incomplete days are flagged and left out of the total
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-06-02", "-t", "8:00"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--week", "-d", "2023-06-01"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("INCOMPLETE"));
+    assert!(output.contains("ERROR some days have incomplete intervals"));
+    assert_eq!(total_line(output), "TOTAL TIME WORKED: 5:30:00");
+
+    // --to without --from is rejected
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--to", "2023-06-05"]);
+    cmd.assert().failure();
+
+    // A backwards range is an error, even for machine readable output
+    for format in ["text", "json"] {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["report", "--from", "2023-06-05", "--to", "2023-05-31", "--format", format]);
+        cmd.assert().failure().stdout("");
+    }
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_overnight_shift() {
+    let test_db_path = get_db_path("test_overnight_shift");
+
+    // 22:00 - 02:00 night shift
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "22:00"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-06-01", "-t", "2:00"]);
+    cmd.assert().success();
+
+    let report = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.arg("report").args(args);
+        let output = get_output(&mut cmd);
+        output.split_whitespace()
+            .last()
+            .unwrap()
+            .to_owned()
+    };
+
+    // Split at midnight by default
+    assert_eq!(report(&["-d", "2023-05-31"]), "2:00:00");
+    assert_eq!(report(&["-d", "2023-06-01"]), "2:00:00");
+
+    // Or attributed whole to the start or end day
+    assert_eq!(report(&["-d", "2023-05-31", "--overnight", "start"]), "4:00:00");
+    assert_eq!(report(&["-d", "2023-06-01", "--overnight", "start"]), "0:00:00");
+    assert_eq!(report(&["-d", "2023-05-31", "--overnight", "end"]), "0:00:00");
+    assert_eq!(report(&["-d", "2023-06-01", "--overnight", "end"]), "4:00:00");
+
+    // Ranges count the shift once
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--from", "2023-05-31", "--to", "2023-06-01"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("│ 2023-05-31 │  Wed  │    2:00:00 │"));
+    assert!(output.contains("│ 2023-06-01 │  Thu  │    2:00:00 │"));
+    assert!(output.contains("TOTAL TIME WORKED: 4:00:00"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_machine_readable_reports() {
+    let test_db_path = get_db_path("test_machine_readable_reports");
+
+    for (io, time) in [("i", "8:00"), ("o", "12:00"), ("i", "13:00")] {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", io, "-d", "2023-05-31", "-t", time]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31", "--format", "json"]);
+    let output = get_output(&mut cmd);
+    let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let day = &report["days"][0];
+    assert_eq!(day["date"], "2023-05-31");
+    assert_eq!(day["records"].as_array().unwrap().len(), 3);
+    assert_eq!(day["records"][0]["io"], "i");
+    assert_eq!(day["records"][0]["timestamp"], "2023-05-31T08:00:00");
+    assert_eq!(day["intervals"][0]["status"], "complete");
+    assert_eq!(day["intervals"][0]["seconds"], 4 * 3600);
+    assert_eq!(day["intervals"][1]["status"], "missing_end");
+    assert!(day["intervals"][1]["end"].is_null());
+    assert!(day["seconds_worked"].is_null());
+    assert_eq!(report["total_seconds"], 0);
+    assert_eq!(report["complete"], false);
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-05-31", "-t", "14:30"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31", "--format", "csv"]);
+    let output = get_output(&mut cmd);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "type,date,id,io,timestamp,start,end,status,seconds,project,tag");
+    assert_eq!(lines[1], "record,2023-05-31,1,i,2023-05-31T08:00:00,,,,,,");
+    assert!(lines.contains(&"interval,2023-05-31,,,,2023-05-31T13:00:00,2023-05-31T14:30:00,complete,5400,,"));
+    assert!(lines.contains(&"day,2023-05-31,,,,,,,19800,,"));
+    assert_eq!(*lines.last().unwrap(), "total,,,,,,,,19800,,");
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_projects_and_tags() {
+    let test_db_path = get_db_path("test_projects_and_tags");
+
+    for args in [
+        &["i", "-t", "8:00", "--project", "acme", "--tag", "meeting"][..],
+        &["o", "-t", "9:00"],
+        &["i", "-t", "9:00", "-p", "acme"],
+        &["o", "-t", "11:30"],
+        &["i", "-t", "13:00", "-p", "globex", "--tag", "meeting"],
+        &["o", "-t", "13:15"],
+        &["i", "-t", "14:00"],
+        &["o", "-t", "15:00"],
+    ] {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", "-d", "2023-05-31"]).args(args);
+        cmd.assert().success();
+    }
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("│ acme                  │  03:30:00  │"));
+    assert!(output.contains("│ globex                │  00:15:00  │"));
+    assert!(output.contains("│ meeting               │  01:15:00  │"));
+    assert!(output.contains("│ (none)                │  01:00:00  │"));
+    assert!(output.contains("│ (none)                │  03:30:00  │"));
+    assert!(output.trim().ends_with("4:45:00"));
+
+    // Fixing the time of an entry keeps its project
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "5", "i", "-d", "2023-05-31", "-t", "12:45", "--yes"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("│ globex                │  00:30:00  │"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+fn read_schema_version(conn: &sqlite::Connection) -> String {
+    let mut stmt = conn.prepare("SELECT value FROM params WHERE parameter = 'schema_version'").unwrap();
+    stmt.next().unwrap();
+    stmt.read::<String, _>(0).unwrap()
+}
+
+#[test]
+fn test_migrate_from_v1_0_2() {
+    let test_db_path = get_db_path("test_migrate_from_v1_0_2");
+    let backup_path = format!("{test_db_path}.v1.bak");
+    let _ = std::fs::remove_file(&backup_path);
+
+    let conn = sqlite::open(&test_db_path).unwrap();
+    conn.execute(include_str!("fixtures/v1_0_2.sql")).unwrap();
+    drop(conn);
+
+    // Old entries are still there after upgrading
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.trim().ends_with("8:30:00"));
+
+    // New columns work on the upgraded database
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-06-01", "-t", "8:00", "-p", "acme"]);
+    cmd.assert().success();
+
+    let conn = sqlite::open(&test_db_path).unwrap();
+    assert_eq!(read_schema_version(&conn), "8");
+
+    // The backup is the untouched 1.0.2 database
+    let backup = sqlite::open(&backup_path).unwrap();
+    let mut stmt = backup.prepare("SELECT COUNT(*) FROM times").unwrap();
+    stmt.next().unwrap();
+    assert_eq!(stmt.read::<i64, _>(0).unwrap(), 4);
+    assert!(backup.prepare("SELECT project FROM times").is_err());
+
+    // Databases from a newer timest are left alone
+    conn.execute("UPDATE params SET value = '999' WHERE parameter = 'schema_version'").unwrap();
+    drop(conn);
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    cmd.assert().failure();
+
+    std::fs::remove_file(backup_path).unwrap();
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_new_database_schema_version() {
+    let test_db_path = get_db_path("test_new_database_schema_version");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report"]);
+    cmd.assert().success();
+
+    let conn = sqlite::open(&test_db_path).unwrap();
+    assert_eq!(read_schema_version(&conn), "8");
+    drop(conn);
+
+    // Fresh databases have nothing to back up
+    assert!(!std::path::Path::new(&format!("{test_db_path}.v1.bak")).exists());
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_config() {
+    let test_db_path = get_db_path("test_config");
+
+    let config = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.arg("config").args(args);
+        cmd
+    };
+
+    let output = get_output(&mut config(&["list"]));
+    assert!(output.contains("check_time = true (default)"));
+    assert!(output.contains("overnight = split (default)"));
+
+    config(&["set", "overnight", "Start"]).assert().success();
+    assert_eq!(get_output(&mut config(&["get", "overnight"])).trim(), "start");
+    config(&["set", "check_time", "no"]).assert().success();
+    assert_eq!(get_output(&mut config(&["get", "check_time"])).trim(), "false");
+
+    // Bad keys and values are rejected and nothing is saved
+    config(&["set", "overnight", "sideways"]).assert().failure();
+    config(&["set", "nonsense", "true"]).assert().failure();
+    config(&["get", "nonsense"]).assert().failure();
+    assert_eq!(get_output(&mut config(&["get", "overnight"])).trim(), "start");
+
+    // Reports follow the saved overnight rule
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "22:00"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-06-01", "-t", "2:00"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).trim().ends_with("4:00:00"));
+
+    config(&["unset", "overnight"]).assert().success();
+    let output = get_output(&mut config(&["list"]));
+    assert!(output.contains("overnight = split (default)"));
+    assert!(output.contains("check_time = false\n"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).trim().ends_with("2:00:00"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_toggle_clock() {
+    let test_db_path = get_db_path("test_toggle_clock");
+
+    let toggle = |date: &str, time: &str| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", "-d", date, "-t", time]);
+        cmd
+    };
+
+    // First entry ever is a clock in, then alternate
+    assert_eq!(get_output(&mut toggle("2023-05-31", "8:00")).trim(), "Clocking in at 2023-05-31 08:00:00");
+    assert_eq!(get_output(&mut toggle("2023-05-31", "12:00")).trim(), "Clocking out at 2023-05-31 12:00:00");
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["punch", "-d", "2023-05-31", "-t", "13:00"]);
+    assert_eq!(get_output(&mut cmd).trim(), "Clocking in at 2023-05-31 13:00:00");
+    toggle("2023-05-31", "17:00").assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).trim().ends_with("8:00:00"));
+
+    // Backdating between a clock in and out can't be inferred
+    toggle("2023-05-31", "9:00").assert().failure();
+
+    // A clock in long ago probably means a forgotten clock out
+    toggle("2023-06-01", "8:00").assert().success();
+    toggle("2023-06-02", "8:00").assert().failure();
+
+    // Saying which one, and forcing it, is always allowed
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-06-02", "-t", "8:00", "--force"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-06-02"]);
+    let output = get_output(&mut cmd);
+    let rows: Vec<&str> = output.trim().lines().skip(4).collect();
+    assert_eq!(rows, ["|  6  |  08:00:00  |  o  |"]);
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_sequence_validation() {
+    let test_db_path = get_db_path("test_sequence_validation");
+
+    let clock = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.arg("clock").args(["-d", "2023-05-31"]).args(args);
+        cmd
+    };
+
+    clock(&["i", "-t", "8:00"]).assert().success();
+    clock(&["o", "-t", "12:00"]).assert().success();
+
+    // Two clock ins in a row
+    let output = clock(&["i", "-t", "10:00"]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("would follow another clock in at 2023-05-31 08:00:00"));
+
+    // Backdated clock out before the first clock in
+    clock(&["o", "-t", "7:00"]).assert().failure();
+
+    // Nothing was saved
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).trim().ends_with("4:00:00"));
+
+    // Fixing an entry into the wrong place, or moving it out of a pair
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "2", "i", "-d", "2023-05-31", "-t", "12:00"]);
+    cmd.assert().failure();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-d", "2023-05-31", "-t", "13:00"]);
+    cmd.assert().failure();
+
+    // Moving an entry within its pair is fine
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-d", "2023-05-31", "-t", "9:00", "--yes"]);
+    cmd.assert().success();
+
+    // Entries on other days far away don't count
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-06-02", "-t", "8:00"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-06-05", "-t", "8:00"]);
+    cmd.assert().success();
+
+    // --force saves anyway
+    clock(&["i", "-t", "10:00", "--force"]).assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("ERROR there are some incomplete intervals"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_time_expressions() {
+    let test_db_path = get_db_path("test_time_expressions");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-t", "yesterday 9am"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "yesterday", "-t", "5:30 pm"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "yesterday"]);
+    assert!(get_output(&mut cmd).trim().ends_with("8:30:00"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "--from", "2 days ago", "--to", "today"]);
+    assert!(get_output(&mut cmd).contains("TOTAL TIME WORKED: 8:30:00"));
+
+    // Times relative to now, without asking a time server
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "check_time", "false"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-t", "-15m"]);
+    cmd.assert().success();
+
+    // Nonsense, and days given twice, are rejected
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-t", "teatime"]);
+    cmd.assert().failure();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-t", "yesterday 6pm", "-d", "today"]);
+    cmd.assert().failure();
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_status() {
+    let test_db_path = get_db_path("test_status");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["status", "--terse"]);
+    assert!(get_output(&mut cmd).starts_with("none\t"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "check_time", "false"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-t", "-1h"]);
+    cmd.assert().success();
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["status", "--terse"]);
+    let output = get_output(&mut cmd);
+    let fields: Vec<&str> = output.trim_end().split('\t').collect();
+    assert_eq!(fields[0], "in");
+    let elapsed: i64 = fields[2].parse().unwrap();
+    assert!((3600..3660).contains(&elapsed));
+    assert_eq!(fields[4], "complete");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["status"]);
+    assert!(get_output(&mut cmd).starts_with("Clocked in since"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["status", "--terse"]);
+    assert!(get_output(&mut cmd).starts_with("out\t"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_prompt() {
+    let test_db_path = get_db_path("test_prompt");
+
+    // Nothing to show before the first entry
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["prompt"]);
+    assert_eq!(get_output(&mut cmd), "\n");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-t", "yesterday 9am", "-p", "acme"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-t", "yesterday 10am"]);
+    cmd.assert().success();
+
+    // check_time is left on, so this would fail if the time server were asked
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["prompt", "{state}", "--clocked-out", "-"]);
+    assert_eq!(get_output(&mut cmd), "-\n");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-t", "yesterday 11am", "-p", "acme"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["prompt", "[{state} {project} since {since}]", "--clocked-out", ""]);
+    assert_eq!(get_output(&mut cmd), "[in acme since 11:00]\n");
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_library_api() {
+    use chrono::NaiveDate;
+    use timest::{IO, IntervalStatus, Timesheet};
+
+    let test_db_path = get_db_path("test_library_api");
+    let date = NaiveDate::from_ymd_opt(2023, 5, 31).unwrap();
+    let at = |hour| date.and_hms_opt(hour, 0, 0).unwrap();
+
+    {
+        let timesheet = Timesheet::open(&test_db_path).unwrap();
+        timesheet.clock(at(8), IO::I, Some("acme"), None, Some("kickoff")).unwrap();
+        assert_eq!(timesheet.infer_io(at(12)).unwrap(), IO::O);
+        timesheet.clock(at(12), IO::O, None, None, None).unwrap();
+        assert_eq!(timesheet.sequence_problems(at(13), IO::O).unwrap().len(), 1);
+        timesheet.clock(at(13), IO::I, None, Some("meeting"), None).unwrap();
+
+        assert_eq!(timesheet.records(date, date).unwrap().len(), 3);
+        let intervals = timesheet.day_intervals(date).unwrap();
+        assert_eq!(intervals[0].project.as_deref(), Some("acme"));
+        assert_eq!(intervals[0].note.as_deref(), Some("kickoff"));
+        assert_eq!(intervals[1].status, IntervalStatus::MissingEnd);
+        assert_eq!(timesheet.seconds_worked(date, date).unwrap(), None);
+
+        timesheet.clock(at(17), IO::O, None, None, None).unwrap();
+        assert_eq!(timesheet.seconds_worked(date, date).unwrap(), Some(8 * 3600));
+    }
+
+    // The CLI sees the same data
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("TOTAL TIME WORKED: 8:00:00"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_exit_codes() {
+    let test_db_path = get_db_path("test_exit_codes");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "8:00"]);
+    cmd.assert().success();
+
+    // No such entry
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "99"]);
+    cmd.assert().code(3);
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "99", "-d", "2023-05-31", "-t", "9:00"]);
+    cmd.assert().code(3);
+
+    // Nobody to confirm a broken sequence. Piped answers don't count
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "9:00"]);
+    cmd.assert().code(5);
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "9:00"]);
+    cmd.write_stdin("y\n");
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("Are you sure"));
+
+    // Another program holds the database
+    let conn = sqlite::open(&test_db_path).unwrap();
+    conn.execute("BEGIN EXCLUSIVE").unwrap();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    cmd.assert().code(4);
+    conn.execute("ROLLBACK").unwrap();
+    drop(conn);
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_fix_and_delete_confirmation() {
+    let test_db_path = get_db_path("test_fix_and_delete_confirmation");
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "8:00", "-p", "acme"]);
+    cmd.assert().success();
+
+    // Missing ids are reported
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "999", "--yes"]);
+    let output = cmd.output().unwrap();
+    assert!(String::from_utf8(output.stderr).unwrap().contains("no entry with id 999"));
+
+    // The change is shown and has to be confirmed, at a terminal or with --yes
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-d", "2023-05-31", "-t", "8:30"]);
+    cmd.write_stdin("y\n");
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("before: 1  2023-05-31 08:00:00  in   project: acme"));
+    assert!(stdout.contains("after:  1  2023-05-31 08:30:00  in   project: acme"));
+    assert!(!stdout.contains("Save this change?"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("08:00:00"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-d", "2023-05-31", "-t", "8:30", "--yes"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("08:30:00"));
+
+    // Deleting without anyone to confirm does nothing
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "1"]);
+    cmd.assert().failure();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "1", "-y"]);
+    assert!(get_output(&mut cmd).contains("delete: 1  2023-05-31 08:30:00  in"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "1", "-y"]);
+    cmd.assert().code(3);
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_partial_fix() {
+    let test_db_path = get_db_path("test_partial_fix");
+    let fix = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["fix", "1", "--yes"]).args(args);
+        get_output(&mut cmd)
+    };
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-23", "-t", "8:00", "-p", "acme"]);
+    cmd.assert().success();
+
+    // Only the time changes, not the date
+    assert!(fix(&["-t", "8:30"]).contains("after:  1  2023-05-23 08:30:00  in   project: acme"));
+    // Only the date changes, not the time
+    assert!(fix(&["-d", "2023-05-24"]).contains("after:  1  2023-05-24 08:30:00  in   project: acme"));
+    // Only the labels change
+    assert!(fix(&["-p", "globex"]).contains("after:  1  2023-05-24 08:30:00  in   project: globex"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-05-24"]);
+    assert!(get_output(&mut cmd).contains("|  1  |  08:30:00  |  i  |"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_undo_redo_history() {
+    let test_db_path = get_db_path("test_undo_redo_history");
+    let run = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(args);
+        get_output(&mut cmd)
+    };
+    let report = || run(&["report", "simple", "-d", "2023-05-31"]);
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["undo"]);
+    assert_eq!(get_output(&mut cmd), "Nothing to undo\n");
+
+    run(&["clock", "i", "-d", "2023-05-31", "-t", "8:00"]);
+    run(&["clock", "o", "-d", "2023-05-31", "-t", "12:00"]);
+    run(&["fix", "2", "-t", "12:30", "--yes"]);
+    run(&["delete", "1", "--yes"]);
+    assert!(!report().contains("08:00:00"));
+
+    // Undo in reverse order
+    assert!(run(&["undo"]).contains("Undid change 4 (delete of entry 1)"));
+    assert!(report().contains("|  1  |  08:00:00  |  i  |"));
+    assert!(run(&["undo"]).contains("Undid change 3 (update of entry 2)"));
+    assert!(report().contains("|  2  |  12:00:00  |  o  |"));
+
+    // Redo what was undone, until something new is changed
+    assert!(run(&["redo"]).contains("Redid change 3 (update of entry 2)"));
+    assert!(report().contains("|  2  |  12:30:00  |  o  |"));
+    run(&["clock", "i", "-d", "2023-05-31", "-t", "13:00"]);
+    assert_eq!(run(&["redo"]), "Nothing to redo\n");
+
+    // Everything is in the history, newest first
+    let history = run(&["history"]);
+    let lines: Vec<&str> = history.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[0].contains("insert") && lines[0].contains("(none) -> 3  2023-05-31 13:00:00  in"));
+    assert!(lines[1].contains("redo of 3"));
+    assert!(lines[4].contains("delete") && lines[4].contains("1  2023-05-31 08:00:00  in  -> (none)"));
+    assert_eq!(run(&["history", "-n", "2"]).lines().count(), 2);
+
+    // The log can't be rewritten
+    let conn = sqlite::open(&test_db_path).unwrap();
+    assert!(conn.execute("DELETE FROM changes").is_err());
+    assert!(conn.execute("UPDATE changes SET entry_id = 5").is_err());
+    drop(conn);
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_trash() {
+    let test_db_path = get_db_path("test_trash");
+    let run = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(args);
+        get_output(&mut cmd)
+    };
+    let report = || run(&["report", "-d", "2023-05-31"]);
+
+    run(&["clock", "i", "-d", "2023-05-31", "-t", "8:00"]);
+    run(&["clock", "o", "-d", "2023-05-31", "-t", "12:00"]);
+    run(&["clock", "o", "-d", "2023-05-31", "-t", "12:01", "--force"]);
+    assert!(report().contains("ERROR"));
+    assert_eq!(run(&["trash", "list"]), "The trash is empty\n");
+
+    // Deleted entries are left out of reports but kept in the trash
+    run(&["delete", "3", "--yes"]);
+    assert!(report().trim().ends_with("4:00:00"));
+    assert!(run(&["trash", "list"]).starts_with("3  2023-05-31 12:01:00  out  (deleted "));
+
+    // and can be brought back
+    assert!(run(&["trash", "restore", "3"]).contains("restored: 3  2023-05-31 12:01:00  out"));
+    assert!(report().contains("ERROR"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["trash", "restore", "3"]);
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("entry 3 is not in the trash"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["trash", "restore", "99"]);
+    cmd.assert().code(3);
+
+    // Purging only removes entries that have been in the trash long enough
+    run(&["delete", "3", "--yes"]);
+    assert!(run(&["trash", "purge", "--older-than", "1d", "--yes"]).starts_with("Nothing in the trash"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["trash", "purge", "--older-than", "forever"]);
+    cmd.assert().failure();
+
+    let conn = sqlite::open(&test_db_path).unwrap();
+    conn.execute("UPDATE times SET deleted_at = '2023-06-01 00:00:00' WHERE id = 3").unwrap();
+    drop(conn);
+    assert_eq!(run(&["trash", "purge", "--yes"]), "Removed 1 entries for good\n");
+    assert_eq!(run(&["trash", "list"]), "The trash is empty\n");
+    assert!(report().trim().ends_with("4:00:00"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_repair() {
+    let test_db_path = get_db_path("test_repair");
+    let clocks: [&[&str]; 6] = [
+        &["i", "-t", "8:00"],
+        &["i", "-t", "12:00", "--force"],
+        &["o", "-t", "13:00"],
+        &["i", "-t", "16:00"],
+        &["o", "-t", "17:00"],
+        &["o", "-t", "18:00", "--force"],
+    ];
+    for args in clocks {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", "-d", "2023-05-31"]).args(args);
+        cmd.assert().success();
+    }
+
+    // Skipping moves on to the next interval, and running out of answers stops
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["repair", "-d", "2023-05-31"]);
+    cmd.write_stdin("s\n");
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("the clock in at 2023-05-31 08:00:00 has no clock out"));
+    assert!(stdout.contains("2) Change entry 2 at 12:00:00 from a clock in to a clock out"));
+    assert!(stdout.contains("the clock out at 2023-05-31 18:00:00 has no clock in"));
+
+    // Add the missing clock out, then delete the extra clock out
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["repair", "-d", "2023-05-31"]);
+    cmd.write_stdin("1\n11:00\n2\n");
+    let stdout = get_output(&mut cmd);
+    assert!(stdout.contains("added: 7  2023-05-31 11:00:00  out"));
+    assert!(stdout.contains("deleted: 6  2023-05-31 18:00:00  out"));
+    assert!(stdout.contains("Nothing left to repair on 2023-05-31"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("TOTAL TIME WORKED: 5:00:00"));
+
+    // Repairs are changes like any other
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.arg("undo");
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("incomplete"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_repair_flip() {
+    let test_db_path = get_db_path("test_repair_flip");
+    let clock = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", "--force"]).args(args);
+        cmd.assert().success();
+    };
+    let repair = |date: &str, answers: &str| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["repair", "-d", date]);
+        cmd.write_stdin(answers);
+        get_output(&mut cmd)
+    };
+    let report = |date: &str| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["report", "-d", date]);
+        get_output(&mut cmd)
+    };
+
+    // A clock out that should have been a clock in, followed by a real one
+    clock(&["o", "-d", "2023-05-31", "-t", "8:00"]);
+    clock(&["o", "-d", "2023-05-31", "-t", "12:00"]);
+    let stdout = repair("2023-05-31", "s\n2\n");
+    assert!(stdout.contains("2) Change entry 1 at 08:00:00 from a clock out to a clock in"));
+    assert!(stdout.contains("changed: 1  2023-05-31 08:00:00  in"));
+    assert!(stdout.contains("Nothing left to repair on 2023-05-31"));
+    assert!(report("2023-05-31").contains("TOTAL TIME WORKED: 4:00:00"));
+
+    // Flipping the earlier clock out would only leave the clock in before it open
+    clock(&["i", "-d", "2023-06-01", "-t", "8:00"]);
+    clock(&["o", "-d", "2023-06-01", "-t", "12:00"]);
+    clock(&["o", "-d", "2023-06-01", "-t", "17:00"]);
+    let stdout = repair("2023-06-01", "2\n");
+    assert!(stdout.contains("the clock out at 2023-06-01 17:00:00 has no clock in"));
+    assert!(!stdout.contains("Change entry"));
+    assert!(stdout.contains("deleted: 5  2023-06-01 17:00:00  out"));
+    assert!(report("2023-06-01").contains("TOTAL TIME WORKED: 4:00:00"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_add() {
+    let test_db_path = get_db_path("test_add");
+    let add = |args: &[&str]| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["add", "-d", "2023-05-31"]).args(args);
+        cmd.output().unwrap()
+    };
+
+    let output = add(&["09:00-10:30", "-p", "acme", "--tag", "meeting"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("added: 1  2023-05-31 09:00:00  in   project: acme  tag: meeting"));
+    assert!(stdout.contains("added: 2  2023-05-31 10:30:00  out  project: acme  tag: meeting"));
+
+    // Right before or after another interval is fine, but not on top of one
+    assert!(add(&["10:30-11:00"]).status.success());
+    assert!(add(&["8:00-9:00"]).status.success());
+    for range in ["10:00-12:00", "8:30-9:15", "9:15-9:45", "7:00-13:00"] {
+        let output = add(&[range]);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8(output.stderr).unwrap().contains("overlaps the interval"));
+    }
+    assert_eq!(add(&["11:00-10:00"]).status.code(), Some(2));
+
+    // A clock out at the same time as a clock in ends the interval before it
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "7:00", "--force"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-05-31", "-t", "8:00"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    assert!(get_output(&mut cmd).contains("TOTAL TIME WORKED: 4:00:00"));
+
+    // A forgotten clock out runs to the end of the day
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "13:00"]);
+    cmd.assert().success();
+    assert_eq!(add(&["15:00-16:00"]).status.code(), Some(1));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
+    let stdout = get_output(&mut cmd);
+    assert_eq!(stdout.matches("|  i  |").count(), 5);
+    assert_eq!(stdout.matches("|  o  |").count(), 4);
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_notes() {
+    let test_db_path = get_db_path("test_notes");
+    let long_note = "Went through the quarterly numbers with the whole finance team";
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "i", "-d", "2023-05-31", "-t", "8:00", "--note", long_note]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["clock", "o", "-d", "2023-05-31", "-t", "9:00"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["add", "10:00-10:30", "-d", "2023-05-31", "-n", "standup"]);
+    cmd.assert().success();
+
+    // Reports cut long notes short
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "simple", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("|  08:00:00  |  i  |  Went through the quarterly numbers with…"));
+    assert!(output.contains("|  10:00:00  |  i  |  standup"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("│  08:00:00 - 09:00:00  │  01:00:00  │ Went through the quarterly nu… │"));
+    assert!(output.contains("│  10:00:00 - 10:30:00  │  00:30:00  │ standup                        │"));
+
+    // JSON has the whole note
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["report", "-d", "2023-05-31", "--format", "json"]);
+    let json: serde_json::Value = serde_json::from_str(&get_output(&mut cmd)).unwrap();
+    assert_eq!(json["days"][0]["records"][0]["note"], long_note);
+    assert_eq!(json["days"][0]["intervals"][0]["note"], long_note);
+    assert_eq!(json["days"][0]["intervals"][1]["note"], "standup");
+
+    // Fixing a note keeps the rest, and undo brings the old one back
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "3", "--note", "retro", "--yes"]);
+    assert!(get_output(&mut cmd).contains("after:  3  2023-05-31 10:00:00  in   note: retro"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.arg("undo");
+    assert!(get_output(&mut cmd).contains("after:  3  2023-05-31 10:00:00  in   note: standup"));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+/// A stand-in time server on localhost that answers with the system time plus
+/// `offset` seconds. Returns its address.
+fn fake_ntp_server(offset: i64) -> String {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let mut request = [0; 48];
+        while let Ok((_, client)) = socket.recv_from(&mut request) {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+            let mut answer = [0; 48];
+            // No leap second warning, version 4, server mode
+            answer[0] = 0b00_100_100;
+            // Stratum 1, with no particular reference
+            answer[1] = 1;
+            // Originate time, echoing the request's transmit time
+            answer[24..32].copy_from_slice(&request[40..48]);
+            // Transmit time, in seconds since 1900
+            answer[40..44].copy_from_slice(&((now + offset + 2_208_988_800) as u32).to_be_bytes());
+            socket.send_to(&answer, client).unwrap();
+        }
+    });
+    address
+}
+
+/// An address nothing answers on.
+fn dead_ntp_server() -> String {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn test_ntp_settings() {
+    let test_db_path = get_db_path("test_ntp_settings");
+    let clock = |io: &str, servers: &str| {
+        // Clocks within the same second put the out before the in, so skip the sequence check
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", io, "--force"])
+            .env("TIMEST_NTP_SERVERS", servers)
+            .env("TIMEST_NTP_TIMEOUT", "1s")
+            .env("TIMEST_NTP_RECHECK", "0s");
+        cmd.output().unwrap()
+    };
+
+    // Servers are tried in order until one answers
+    let dead = dead_ntp_server();
+    let good = fake_ntp_server(0);
+    assert!(clock("i", &format!("{dead},{good}")).status.success());
+
+    // A clock too far off has to be confirmed, and nobody is there to
+    let slow = fake_ntp_server(3600);
+    let output = clock("o", &slow);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("system clock is slow by 1h, more than the 15m allowed"));
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "ntp_tolerance", "2h"]);
+    cmd.assert().success();
+    assert!(clock("o", &slow).status.success());
+
+    // No server answering is a failed check too
+    let output = clock("i", &dead);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("failed to communicate with time server"));
+
+    // The environment wins over saved settings
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "list"]).env("TIMEST_NTP_TIMEOUT", "2s");
+    let output = get_output(&mut cmd);
+    assert!(output.contains("ntp_tolerance = 2h\n"));
+    assert!(output.contains("ntp_timeout = 2s (from TIMEST_NTP_TIMEOUT)"));
+    assert!(output.contains("ntp_servers = time.nist.gov:123 (default)"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "ntp_timeout", "0s"]);
+    cmd.assert().failure();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "ntp_tolerance", "--", "-5 minutes"]);
+    cmd.assert().failure();
+
+    // A bad saved value falls back to the default, and can still be fixed
+    let conn = sqlite::open(&test_db_path).unwrap();
+    conn.execute("UPDATE params SET value = '-5m' WHERE parameter = 'ntp_tolerance'").unwrap();
+    drop(conn);
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "get", "ntp_tolerance"]);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "15m\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Warning: bad setting saved in database"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "unset", "ntp_tolerance"]);
+    cmd.assert().success();
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "get", "ntp_tolerance"]);
+    cmd.assert().success().stderr("");
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_clock_check_cache() {
+    let test_db_path = get_db_path("test_clock_check_cache");
+    let clock = |io: &str, servers: &str| {
+        // Clocks within the same second put the out before the in, so skip the sequence check
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", io, "--force"]).env("TIMEST_NTP_SERVERS", servers).env("TIMEST_NTP_TIMEOUT", "1s");
+        cmd.output().unwrap()
+    };
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.arg("status");
+    assert!(get_output(&mut cmd).contains("System clock: not checked yet"));
+
+    // A recent check is used instead of asking again
+    let slow = fake_ntp_server(120);
+    let dead = dead_ntp_server();
+    assert!(clock("i", &slow).status.success());
+    assert!(clock("o", &dead).status.success());
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.arg("status");
+    assert!(get_output(&mut cmd).contains("System clock: 2m slow (checked "));
+
+    // Until it is older than ntp_recheck
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "ntp_recheck", "0s"]);
+    cmd.assert().success();
+    assert_eq!(clock("i", &dead).status.code(), Some(6));
+    let fast = fake_ntp_server(-5);
+    assert!(clock("i", &fast).status.success());
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.arg("status");
+    assert!(get_output(&mut cmd).contains("System clock: 5s fast (checked "));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_correct_time() {
+    let test_db_path = get_db_path("test_correct_time");
+    let clock = |io: &str, servers: &str| {
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", io])
+            .env("TIMEST_NTP_SERVERS", servers)
+            .env("TIMEST_NTP_TIMEOUT", "1s")
+            .env("TIMEST_NTP_RECHECK", "0s")
+            .env("TIMEST_CORRECT_TIME", "true");
+        cmd.output().unwrap()
+    };
+
+    // The system clock is two minutes slow, so the entry is two minutes later
+    let output = clock("i", &fake_ntp_server(120));
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("using the time server"));
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["history", "-n", "1"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("in   corrected: +120s"));
+
+    // Without a time server to ask, it is the same as any failed check
+    assert_eq!(clock("o", &dead_ntp_server()).status.code(), Some(6));
+
+    // Setting the time by hand drops the correction
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-t", "8:00", "--yes"]);
+    let output = get_output(&mut cmd);
+    assert!(output.contains("corrected: +120s"));
+    assert!(!output.lines().any(|line| line.starts_with("after:") && line.contains("corrected")));
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}
+
+#[test]
+fn test_time_check_policy() {
+    let test_db_path = get_db_path("test_time_check_policy");
+    let dead = dead_ntp_server();
+    let clock = |args: &[&str], policy: Option<&str>| {
+        // Clocks within the same second put the out before the in, so skip the sequence check
+        let mut cmd = new_cmd(&test_db_path);
+        cmd.args(["clock", "--force"]).args(args)
+            .env("TIMEST_NTP_SERVERS", &dead)
+            .env("TIMEST_NTP_TIMEOUT", "1s")
+            .env("TIMEST_NTP_RECHECK", "0s");
+        if let Some(policy) = policy {
+            cmd.env("TIMEST_TIME_CHECK_FAILURE", policy);
+        }
+        // Piped answers are ignored, since nobody is at a terminal
+        cmd.write_stdin("y\n");
+        cmd.output().unwrap()
+    };
+
+    let output = clock(&["i"], None);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("Are you sure"));
+    assert_eq!(clock(&["i"], Some("reject")).status.code(), Some(6));
+
+    let output = clock(&["i", "--yes"], None);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Carrying on because of --yes"));
+    let output = clock(&["o"], Some("accept"));
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Carrying on because time_check_failure is accept"));
+    assert!(clock(&["i"], Some("skip-check")).status.success());
+
+    // --no-input leaves every question unanswered
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["delete", "1", "--no-input"]);
+    cmd.write_stdin("y\n");
+    cmd.assert().code(7);
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["--no-input", "fix", "1", "-y", "--note", "standup"]);
+    cmd.assert().success();
+
+    // Confirming a fix doesn't carry on past a failed clock check
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["fix", "1", "-y", "-t", "-1m"])
+        .env("TIMEST_NTP_SERVERS", &dead)
+        .env("TIMEST_NTP_TIMEOUT", "1s")
+        .env("TIMEST_NTP_RECHECK", "0s");
+    cmd.assert().code(6);
+
+    let mut cmd = new_cmd(&test_db_path);
+    cmd.args(["config", "set", "time_check_failure", "sometimes"]);
+    cmd.assert().failure();
+
+    std::fs::remove_file(test_db_path).expect("Could not clean up test database. See $TIMEST_TEST");
+}